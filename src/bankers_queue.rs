@@ -0,0 +1,231 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use queue::Queue;
+use stream::{self, Stream};
+
+/// Okasaki's banker's queue, from section 6.3.2.
+///
+/// Like the batched queue it keeps a front and a reversed rear, but
+/// both are streams, and as soon as the rear grows longer than the front
+/// the two are joined into `front ++ reverse rear` without evaluating
+/// any of it. The reversal only runs once the front has been used up to
+/// it, by which time enough cheap operations have gone by to pay for
+/// it, and since streams remember their cells every version sharing
+/// that front shares the one reversal. So `snoc`, `head` and `tail` are
+/// O(1) amortized even when old versions are used again and again.
+/// Like `Stream` itself, it only comes in an `Rc` flavour.
+pub struct BankersQueue<E: 'static> {
+    front_len: usize,
+    front: Stream<E>,
+    rear_len: usize,
+    /// The back of the queue, last element first.
+    rear: Stream<E>,
+}
+
+impl<E: Clone + 'static> BankersQueue<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+
+    /// Starts rotating the rear onto the front once it gets longer.
+    fn checked(front_len: usize, front: Stream<E>, rear_len: usize, rear: Stream<E>) -> Self {
+        if rear_len <= front_len {
+            BankersQueue { front_len, front, rear_len, rear }
+        } else {
+            BankersQueue {
+                front_len: front_len + rear_len,
+                front: front.append(&rear.reverse()),
+                rear_len: 0,
+                rear: Stream::empty(),
+            }
+        }
+    }
+}
+
+impl<E> BankersQueue<E> {
+    /// Borrows the elements from the front of the queue to the back,
+    /// forcing the front as it goes.
+    pub fn iter(&self) -> Iter<'_, E> {
+        Iter { front: self.front.iter(), rear: self.rear.iter().collect() }
+    }
+}
+
+impl<E> Clone for BankersQueue<E> {
+    fn clone(&self) -> Self {
+        BankersQueue {
+            front_len: self.front_len,
+            front: self.front.clone(),
+            rear_len: self.rear_len,
+            rear: self.rear.clone(),
+        }
+    }
+}
+
+impl<E: Clone + 'static> Queue for BankersQueue<E> {
+    type Item = E;
+
+    fn empty() -> Self {
+        BankersQueue { front_len: 0, front: Stream::empty(), rear_len: 0, rear: Stream::empty() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    fn snoc(&self, x: E) -> Self {
+        Self::checked(self.front_len, self.front.clone(), self.rear_len + 1, self.rear.cons(x))
+    }
+
+    fn head(&self) -> Option<&E> {
+        self.front.first()
+    }
+
+    fn tail(&self) -> Self {
+        if self.is_empty() {
+            return self.clone();
+        }
+        Self::checked(self.front_len - 1, self.front.rest(), self.rear_len, self.rear.clone())
+    }
+}
+
+pub struct Iter<'a, E: 'static> {
+    front: stream::Iter<'a, E>,
+    /// The back of the queue, last element first.
+    rear: Vec<&'a E>,
+}
+
+impl<'a, E> Iterator for Iter<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.front.next().or_else(|| self.rear.pop())
+    }
+}
+
+/// Owning iterator over a `BankersQueue`, which hands out the elements
+/// by taking the `tail` of the queue one step at a time.
+pub struct IntoIter<E: 'static> {
+    queue: BankersQueue<E>,
+}
+
+impl<E: Clone + 'static> Iterator for IntoIter<E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let x = self.queue.head()?.clone();
+        self.queue = self.queue.tail();
+        Some(x)
+    }
+}
+
+impl<E: Clone + 'static> Default for BankersQueue<E> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone + fmt::Debug + 'static> fmt::Debug for BankersQueue<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E: Clone + PartialEq + 'static> PartialEq for BankersQueue<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.front_len + self.rear_len == other.front_len + other.rear_len
+            && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, E: Clone + 'static> IntoIterator for &'a BankersQueue<E> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E>;
+
+    fn into_iter(self) -> Iter<'a, E> {
+        self.iter()
+    }
+}
+
+impl<E: Clone + 'static> IntoIterator for BankersQueue<E> {
+    type Item = E;
+    type IntoIter = IntoIter<E>;
+
+    fn into_iter(self) -> IntoIter<E> {
+        IntoIter { queue: self }
+    }
+}
+
+/// The first element of the iterator ends up at the head of the queue.
+impl<E: Clone + 'static> FromIterator<E> for BankersQueue<E> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        let elements = iter.into_iter().collect::<Vec<_>>();
+        let len = elements.len();
+        let front = elements.into_iter().rev().fold(Stream::empty(), |s, x| s.cons(x));
+        BankersQueue { front_len: len, front, rear_len: 0, rear: Stream::empty() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use queue::BatchedQueue;
+    use queue::checks::{self, drain, queue_of, Counted};
+
+    #[test]
+    fn behaves_as_a_fifo() {
+        checks::check_fifo::<BankersQueue<usize>>();
+    }
+
+    #[test]
+    fn rear_never_outgrows_front() {
+        let mut q = BankersQueue::new();
+        for i in 0..100 {
+            q = q.snoc(i);
+            assert!(q.rear_len <= q.front_len);
+        }
+        for _ in 0..50 {
+            q = q.tail();
+            assert!(q.rear_len <= q.front_len);
+        }
+        assert_eq!((50..100).collect::<Vec<_>>(), q.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rotation_is_lazy() {
+        let q = queue_of::<BankersQueue<_>>(7);
+        assert_eq!(Some(&0), q.head());
+        assert!(!q.front.rest().rest().rest().is_forced());
+    }
+
+    #[test]
+    fn reusing_a_version_shares_its_reversal() {
+        // With 63 elements every rotation has already been set up and
+        // the rear is empty, so all the work left is in the shared front.
+        let clones = Rc::new(Cell::new(0));
+        let elements = (0..63).map(|_| Counted(clones.clone())).collect::<Vec<_>>();
+
+        let bankers = elements.iter().cloned().fold(BankersQueue::new(), |q, x| q.snoc(x));
+        clones.set(0);
+        drain(&bankers);
+        let once = clones.get();
+        for _ in 0..10 {
+            drain(&bankers);
+        }
+        assert_eq!(once, clones.get());
+
+        // The batched queue reverses its rear again for every reuse.
+        let batched = elements.iter().cloned().fold(BatchedQueue::new(), |q, x| q.snoc(x));
+        clones.set(0);
+        drain(&batched);
+        let once = clones.get();
+        for _ in 0..10 {
+            drain(&batched);
+        }
+        assert_eq!(11 * once, clones.get());
+    }
+}
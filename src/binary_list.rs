@@ -0,0 +1,437 @@
+use std::fmt;
+
+use list::{self, IndexOutOfRange, PersistentList, Sequence};
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+
+/// A complete binary tree with the elements in its leaves.
+enum CompleteTree<E, P: SharedPointerKind> {
+    Leaf(E),
+    Node(usize, SharedPointer<CompleteTree<E, P>, P>, SharedPointer<CompleteTree<E, P>, P>),
+}
+
+type TreePointer<E, P> = SharedPointer<CompleteTree<E, P>, P>;
+
+fn size<E, P: SharedPointerKind>(tree: &CompleteTree<E, P>) -> usize {
+    match *tree {
+        CompleteTree::Leaf(_) => 1,
+        CompleteTree::Node(size, _, _) => size,
+    }
+}
+
+fn link<E, P: SharedPointerKind>(t1: TreePointer<E, P>, t2: TreePointer<E, P>) -> TreePointer<E, P> {
+    P::new(CompleteTree::Node(size(&*t1) + size(&*t2), t1, t2))
+}
+
+enum Digit<E, P: SharedPointerKind> {
+    Zero,
+    One(TreePointer<E, P>),
+}
+
+type Spine<E, P> = PersistentList<Digit<E, P>, P>;
+
+impl<E, P: SharedPointerKind> Clone for Digit<E, P> {
+    fn clone(&self) -> Self {
+        match *self {
+            Digit::Zero => Digit::Zero,
+            Digit::One(ref tree) => Digit::One(tree.clone()),
+        }
+    }
+}
+
+/// The binary random-access list from chapter 9 of Okasaki.
+///
+/// Elements are kept in complete binary trees, one for each `One`
+/// digit of the list's length written in binary, least significant
+/// digit first. Every operation, including `cons`, `first` and
+/// `rest`, takes O(log n).
+pub struct BinaryList<E, P: SharedPointerKind = RcK>(Spine<E, P>);
+
+impl<E: Clone> BinaryList<E> {
+    pub fn new() -> Self {
+        BinaryList(PersistentList::new())
+    }
+}
+
+impl<E: Clone> BinaryList<E, ArcK> {
+    pub fn new_sync() -> Self {
+        BinaryList(PersistentList::new_sync())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> BinaryList<E, P> {
+    pub fn lookup(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        let mut offset = index;
+        for digit in self.0.iter() {
+            if let Digit::One(ref tree) = *digit {
+                let size = size(&**tree);
+                if offset < size {
+                    return Ok(lookup_tree::<E, P>(tree, offset));
+                }
+                offset -= size;
+            }
+        }
+        Err(IndexOutOfRange { index, len: index - offset })
+    }
+
+    /// Removes the first tree, splitting larger trees as needed; the
+    /// inverse of adding a tree with `cons`.
+    fn uncons_tree(&self) -> Option<(TreePointer<E, P>, Spine<E, P>)> {
+        let mut zeros = 0;
+        let mut digits = self.0.clone();
+        let found = loop {
+            match digits.first() {
+                None => return None,
+                Some(&Digit::Zero) => zeros += 1,
+                Some(Digit::One(tree)) => break tree.clone(),
+            }
+            digits = digits.rest();
+        };
+
+        let rest = digits.rest();
+        let mut remainder = if rest.is_empty() { rest } else { rest.cons(Digit::Zero) };
+        let mut tree = found;
+        for _ in 0..zeros {
+            let (t1, t2) = match *tree {
+                CompleteTree::Node(_, ref t1, ref t2) => (t1.clone(), t2.clone()),
+                CompleteTree::Leaf(_) => unreachable!("trees below a zero digit are nodes"),
+            };
+            remainder = remainder.cons(Digit::One(t2));
+            tree = t1;
+        }
+        Some((tree, remainder))
+    }
+}
+
+fn lookup_tree<'a, E, P>(tree: &'a TreePointer<E, P>, index: usize) -> &'a E
+where E: 'a,
+      P: SharedPointerKind + 'a,
+{
+    let (mut tree, mut index) = (tree, index);
+    loop {
+        match **tree {
+            CompleteTree::Leaf(ref x) => return x,
+            CompleteTree::Node(size, ref t1, ref t2) => {
+                if index < size / 2 {
+                    tree = t1;
+                } else {
+                    tree = t2;
+                    index -= size / 2;
+                }
+            },
+        }
+    }
+}
+
+fn update_tree<E, P>(tree: &TreePointer<E, P>, index: usize, val: E) -> TreePointer<E, P>
+where P: SharedPointerKind,
+{
+    match **tree {
+        CompleteTree::Leaf(_) => P::new(CompleteTree::Leaf(val)),
+        CompleteTree::Node(size, ref t1, ref t2) => {
+            if index < size / 2 {
+                P::new(CompleteTree::Node(size, update_tree::<E, P>(t1, index, val), t2.clone()))
+            } else {
+                P::new(CompleteTree::Node(size, t1.clone(), update_tree::<E, P>(t2, index - size / 2, val)))
+            }
+        },
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for BinaryList<E, P> {
+    fn clone(&self) -> Self {
+        BinaryList(self.0.clone())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for BinaryList<E, P> {
+    type Iter<'a> = Iter<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        BinaryList(PersistentList::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn cons(&self, el: E) -> Self {
+        // Adding one to a binary number: every leading `One` carries
+        // into the next digit.
+        let mut carry = P::new(CompleteTree::Leaf(el));
+        let mut zeros = 0;
+        let mut digits = self.0.clone();
+        loop {
+            let next = match digits.first() {
+                None | Some(&Digit::Zero) => break,
+                Some(Digit::One(tree)) => tree.clone(),
+            };
+            carry = link::<E, P>(carry, next);
+            zeros += 1;
+            digits = digits.rest();
+        }
+
+        let digits = digits.rest().cons(Digit::One(carry));
+        BinaryList((0..zeros).fold(digits, |digits, _| digits.cons(Digit::Zero)))
+    }
+
+    fn first(&self) -> Option<&E> {
+        let mut tree = self.0.iter().filter_map(|digit| match *digit {
+            Digit::Zero => None,
+            Digit::One(ref tree) => Some(tree),
+        }).next()?;
+        loop {
+            match **tree {
+                CompleteTree::Leaf(ref x) => return Some(x),
+                CompleteTree::Node(_, ref t1, _) => tree = t1,
+            }
+        }
+    }
+
+    fn rest(&self) -> Self {
+        match self.uncons_tree() {
+            None => self.clone(),
+            Some((_, rest)) => BinaryList(rest),
+        }
+    }
+
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let mut offset = index;
+        let mut skipped = Vec::new();
+        let mut digits = self.0.clone();
+        loop {
+            let digit = match digits.first() {
+                None => return Err(IndexOutOfRange { index, len: index - offset }),
+                Some(digit) => digit.clone(),
+            };
+            digits = digits.rest();
+            if let Digit::One(ref tree) = digit {
+                let size = size(&**tree);
+                if offset < size {
+                    let updated = digits.cons(Digit::One(update_tree::<E, P>(tree, offset, val)));
+                    let digits = skipped.into_iter().rev().fold(updated, |digits, digit| digits.cons(digit));
+                    return Ok(BinaryList(digits));
+                }
+                offset -= size;
+            }
+            skipped.push(digit);
+        }
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        let prefix = self.iter().cloned().collect::<Vec<_>>();
+        prefix.into_iter().rev().fold(other.clone(), |list, x| list.cons(x))
+    }
+
+    fn iter(&self) -> Iter<'_, E, P> {
+        Iter { digits: self.0.iter(), pending: Vec::new() }
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|digit| match *digit {
+            Digit::Zero => 0,
+            Digit::One(ref tree) => size(&**tree),
+        }).sum()
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.lookup(index).ok()
+    }
+
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        self.lookup(index)
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    digits: list::Iter<'a, Digit<E, P>, P>,
+    pending: Vec<&'a CompleteTree<E, P>>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        loop {
+            match self.pending.pop() {
+                Some(CompleteTree::Leaf(x)) => return Some(x),
+                Some(CompleteTree::Node(_, t1, t2)) => {
+                    self.pending.push(t2);
+                    self.pending.push(t1);
+                },
+                None => match self.digits.next() {
+                    Some(Digit::One(tree)) => self.pending.push(tree),
+                    Some(Digit::Zero) => {},
+                    None => return None,
+                },
+            }
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for BinaryList<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for BinaryList<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for BinaryList<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    fn binary_list_of(n: usize) -> BinaryList<usize> {
+        (0..n).rev().fold(BinaryList::new(), |l, x| l.cons(x))
+    }
+
+    fn digits(l: &BinaryList<usize>) -> Vec<usize> {
+        l.0.iter().map(|digit| match *digit {
+            Digit::Zero => 0,
+            Digit::One(_) => 1,
+        }).collect()
+    }
+
+    fn trees(l: &BinaryList<usize>) -> Vec<Rc<CompleteTree<usize, RcK>>> {
+        l.0.iter().filter_map(|digit| match *digit {
+            Digit::Zero => None,
+            Digit::One(ref tree) => Some(Rc::clone(tree)),
+        }).collect()
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let l: BinaryList<u8> = BinaryList::new();
+        assert_eq!(l.first(), None);
+    }
+
+    #[test]
+    fn first_on_singleton() {
+        let l = BinaryList::new().cons(1);
+        assert_eq!(l.first(), Some(&1));
+    }
+
+    #[test]
+    fn does_not_move_on_cons() {
+        let l = BinaryList::new();
+        let l2 = l.cons(1);
+        assert!(l.is_empty());
+        assert_eq!(l2.first(), Some(&1));
+    }
+
+    #[test]
+    fn rest_on_nil_is_nil() {
+        let l: BinaryList<u8> = BinaryList::new();
+        assert!(l.rest().is_empty());
+    }
+
+    #[test]
+    fn rest_on_list_drops_first_item() {
+        let l = BinaryList::new().cons(1).cons(2);
+        let rest = l.rest();
+        assert_eq!(rest.first(), Some(&1));
+    }
+
+    #[test]
+    fn digits_count_in_binary() {
+        assert_eq!(vec![1], digits(&binary_list_of(1)));
+        assert_eq!(vec![0, 1], digits(&binary_list_of(2)));
+        assert_eq!(vec![1, 1], digits(&binary_list_of(3)));
+        assert_eq!(vec![0, 0, 1], digits(&binary_list_of(4)));
+        assert_eq!(vec![1, 1], digits(&binary_list_of(4).rest()));
+        assert!(digits(&binary_list_of(1).rest()).is_empty());
+    }
+
+    #[test]
+    fn rest_walks_every_element() {
+        let mut l = binary_list_of(37);
+        for i in 0..37 {
+            assert_eq!(Some(&i), l.first());
+            assert_eq!(37 - i, l.len());
+            l = l.rest();
+        }
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn lookup_every_index() {
+        let l = binary_list_of(100);
+        for i in 0..100 {
+            assert_eq!(Ok(&i), l.lookup(i).map_err(|_| ()));
+        }
+        assert!(l.lookup(100).is_err());
+        assert_eq!((0..100).collect::<Vec<_>>(), l.iter().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn update_copies_changed_node() {
+        // Three elements are a single leaf followed by a pair.
+        let l = binary_list_of(3);
+        let new_l = l.update(0, 4).unwrap();
+        assert_eq!(new_l.first(), Some(&4));
+        assert_eq!(new_l.nth(1), Some(&1));
+
+        assert!(Rc::ptr_eq(&trees(&l)[1], &trees(&new_l)[1]));
+    }
+
+    #[test]
+    fn update_copies_all_dependent_nodes() {
+        let l = binary_list_of(3);
+        let new_l = l.update(1, 4).unwrap();
+        assert_eq!(new_l.first(), Some(&0));
+        assert_eq!(new_l.nth(1), Some(&4));
+        assert_eq!(new_l.nth(2), Some(&2));
+
+        assert!(Rc::ptr_eq(&trees(&l)[0], &trees(&new_l)[0]));
+        match (&*trees(&l)[1], &*trees(&new_l)[1]) {
+            (CompleteTree::Node(_, _, r1), CompleteTree::Node(_, _, r2)) => assert!(Rc::ptr_eq(r1, r2)),
+            _ => panic!("expected nodes"),
+        }
+        assert!(l.update(3, 4).is_err());
+    }
+
+    #[test]
+    fn concat_joins_two_lists() {
+        let xs = BinaryList::new().cons(1).cons(2);
+        let ys = BinaryList::new().cons(3).cons(4);
+
+        let zs = ys.concat(&xs);
+        assert_eq!(vec![&4, &3, &2, &1], zs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concat_leaves_both_lists_usable() {
+        let xs = BinaryList::new().cons(1).cons(2);
+        let ys = BinaryList::new().cons(3).cons(4);
+
+        let _zs = ys.concat(&xs);
+        assert_eq!(vec![&2, &1], xs.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&4, &3], ys.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn large_list() {
+        let l = binary_list_of(1_000_000);
+        assert_eq!(Ok(&765_432), l.lookup(765_432).map_err(|_| ()));
+        let l = l.update(999_999, 0).unwrap();
+        assert_eq!(Some(&0), l.last());
+        assert_eq!(999_999, l.rest().len());
+    }
+}
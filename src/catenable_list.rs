@@ -0,0 +1,311 @@
+use std::fmt;
+use std::rc::Rc;
+
+use bankers_queue::BankersQueue;
+use list::{IndexOutOfRange, Sequence};
+use queue::Queue;
+use util::Susp;
+
+struct Node<E: 'static> {
+    head: E,
+    children: BankersQueue<Susp<CatenableList<E>>>,
+}
+
+/// Okasaki's catenable list, built by structural bootstrapping, from
+/// section 10.2.1.
+///
+/// A non-empty list is its first element together with a queue of the
+/// suspended catenable lists that follow it, so appending one list to
+/// another only adds it to a queue. `cons`, `snoc`, `concat` and `first`
+/// are O(1). `rest` links the first queued list to a suspension that
+/// links the others once it is needed, and is O(1) amortized: the queue
+/// and the suspensions both remember their work, so using an old version
+/// again doesn't repeat it. Unlike the other sequences it has no
+/// `new_sync`, since both of those are `Rc` based.
+pub struct CatenableList<E: 'static>(Option<Rc<Node<E>>>);
+
+impl<E: Clone + 'static> CatenableList<E> {
+    pub fn new() -> Self {
+        CatenableList(None)
+    }
+
+    fn singleton(el: E) -> Self {
+        CatenableList(Some(Rc::new(Node { head: el, children: BankersQueue::empty() })))
+    }
+
+    /// Hangs the suspended `other` off the root of `self`, which must be
+    /// non-empty, as must `other` once forced.
+    fn link(&self, other: Susp<Self>) -> Self {
+        match self.0 {
+            None => other.force().clone(),
+            Some(ref node) => CatenableList(Some(Rc::new(Node {
+                head: node.head.clone(),
+                children: node.children.snoc(other),
+            }))),
+        }
+    }
+
+    /// Links the first list in `queue` to a suspension that links the
+    /// rest of them.
+    fn link_all(queue: &BankersQueue<Susp<Self>>) -> Self {
+        let first = queue.head().expect("only non-empty queues are linked").force().clone();
+        let rest = queue.tail();
+        if rest.is_empty() {
+            first
+        } else {
+            first.link(Susp::delay(move || Self::link_all(&rest)))
+        }
+    }
+
+    /// Adds an element to the end of the list.
+    pub fn snoc(&self, el: E) -> Self {
+        self.concat(&CatenableList::singleton(el))
+    }
+}
+
+impl<E> Clone for CatenableList<E> {
+    fn clone(&self) -> Self {
+        CatenableList(self.0.clone())
+    }
+}
+
+impl<E: Clone + 'static> Sequence<E> for CatenableList<E> {
+    type Iter<'a> = Iter<'a, E> where E: 'a;
+
+    fn empty() -> Self {
+        CatenableList(None)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn cons(&self, el: E) -> Self {
+        CatenableList::singleton(el).concat(self)
+    }
+
+    fn first(&self) -> Option<&E> {
+        self.0.as_ref().map(|node| &node.head)
+    }
+
+    fn rest(&self) -> Self {
+        match self.0 {
+            Some(ref node) if !node.children.is_empty() => Self::link_all(&node.children),
+            _ => Self::empty(),
+        }
+    }
+
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let mut elements = self.iter().cloned().collect::<Vec<_>>();
+        let len = elements.len();
+        match elements.get_mut(index) {
+            None => return Err(IndexOutOfRange { index, len }),
+            Some(el) => *el = val,
+        }
+        Ok(elements.into_iter().fold(Self::empty(), |list, x| list.snoc(x)))
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        if other.is_empty() {
+            self.clone()
+        } else {
+            self.link(Susp::forced(other.clone()))
+        }
+    }
+
+    fn iter(&self) -> Iter<'_, E> {
+        Iter { pending: self.0.iter().map(|node| &**node).collect() }
+    }
+}
+
+/// Walks the elements in order: each node's head, then the lists
+/// queued below it from front to back, forcing them as it reaches them.
+pub struct Iter<'a, E: 'static> {
+    pending: Vec<&'a Node<E>>,
+}
+
+impl<'a, E> Iterator for Iter<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        let node = self.pending.pop()?;
+        // Push the queued lists in reverse, so the first is popped next.
+        let mark = self.pending.len();
+        self.pending.extend(node.children.iter().filter_map(|list| list.force().0.as_deref()));
+        self.pending[mark..].reverse();
+        Some(&node.head)
+    }
+}
+
+impl<E: Clone + 'static> Default for CatenableList<E> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone + fmt::Debug + 'static> fmt::Debug for CatenableList<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E: Clone + PartialEq + 'static> PartialEq for CatenableList<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    /// Counts how often it is cloned, which happens once for every node
+    /// `link` copies.
+    #[derive(Debug)]
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Counted(self.0.clone())
+        }
+    }
+
+    fn catenable_list_of(n: usize) -> CatenableList<usize> {
+        (0..n).fold(CatenableList::new(), |l, x| l.snoc(x))
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let l: CatenableList<u8> = CatenableList::new();
+        assert_eq!(l.first(), None);
+        assert!(l.rest().is_empty());
+    }
+
+    #[test]
+    fn cons_and_snoc_add_to_either_end() {
+        let l = CatenableList::new().snoc(2).cons(1).snoc(3);
+        assert_eq!(vec![&1, &2, &3], l.iter().collect::<Vec<_>>());
+        assert_eq!(l.first(), Some(&1));
+    }
+
+    #[test]
+    fn rest_walks_every_element() {
+        let mut l = catenable_list_of(50);
+        for i in 0..50 {
+            assert_eq!(Some(&i), l.first());
+            assert_eq!(50 - i, l.len());
+            l = l.rest();
+        }
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn concat_joins_two_lists() {
+        let xs = CatenableList::new().cons(1).cons(2);
+        let ys = CatenableList::new().cons(3).cons(4);
+
+        let zs = ys.concat(&xs);
+        assert_eq!(vec![&4, &3, &2, &1], zs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concat_leaves_both_lists_usable() {
+        let xs = CatenableList::new().cons(1).cons(2);
+        let ys = CatenableList::new().cons(3).cons(4);
+
+        let _zs = ys.concat(&xs);
+        assert_eq!(vec![&2, &1], xs.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&4, &3], ys.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concat_with_empty_shares_the_other_list() {
+        let xs = catenable_list_of(3);
+        let empty = CatenableList::new();
+        assert_eq!(xs, xs.concat(&empty));
+        assert_eq!(xs, empty.concat(&xs));
+    }
+
+    #[test]
+    fn nested_concats_keep_order() {
+        let l = (0..10).fold(CatenableList::new(), |l, i| {
+            l.concat(&catenable_list_of(i))
+        });
+        let expected = (0..10).flat_map(|i| 0..i).collect::<Vec<_>>();
+        assert_eq!(expected, l.iter().cloned().collect::<Vec<_>>());
+
+        let mut l = l;
+        for x in expected {
+            assert_eq!(Some(&x), l.first());
+            l = l.rest();
+        }
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn update_leaves_original_alone() {
+        let l = catenable_list_of(5);
+        let new_l = l.update(2, 9).unwrap();
+        assert_eq!(vec![&0, &1, &9, &3, &4], new_l.iter().collect::<Vec<_>>());
+        assert_eq!(Some(&2), l.nth(2));
+        assert!(l.update(5, 9).is_err());
+    }
+
+    #[test]
+    fn repeated_appends_and_rests() {
+        let mut l = (0..100_000).fold(CatenableList::new(), |l, x| {
+            l.concat(&CatenableList::new().cons(x))
+        });
+        for i in 0..100_000 {
+            assert_eq!(Some(&i), l.first());
+            l = l.rest();
+        }
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn rest_of_an_old_version_is_cheap_every_time() {
+        // Linking every queued singleton straight away would copy all
+        // their heads on each `rest` of the same version.
+        let clones = Rc::new(Cell::new(0));
+        let l = (0..1000).fold(CatenableList::new(), |l, _| l.snoc(Counted(clones.clone())));
+
+        clones.set(0);
+        for _ in 0..100 {
+            assert!(!l.rest().is_empty());
+        }
+        assert!(clones.get() <= 100);
+    }
+
+    #[test]
+    fn deep_list_drops_without_overflow() {
+        // Walking a list built by snocs forces one suspended link per
+        // element into one long chain.
+        let l = catenable_list_of(250_000).rest();
+        assert_eq!(249_999, l.iter().count());
+        drop(l);
+
+        let l = (0..250_000).fold(CatenableList::new(), |l, x| l.cons(x));
+        assert_eq!(Some(&249_999), l.first());
+    }
+
+    #[test]
+    fn unforced_rests_drop_without_overflow() {
+        let l = (0..250_000).fold(CatenableList::new(), |l, x| l.cons(x));
+        let l = (0..1000).fold(l, |l, _| l.rest());
+        assert_eq!(Some(&248_999), l.first());
+        drop(l);
+
+        // Each `rest` leaves the previous list queued behind a link that
+        // hasn't been forced, so these nest as deep as the list is long.
+        let mut l = CatenableList::new();
+        for i in 0..250_000 {
+            l = CatenableList::new().cons(i).snoc(i).concat(&l).rest();
+        }
+        assert_eq!(Some(&249_999), l.first());
+        drop(l);
+    }
+}
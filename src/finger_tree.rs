@@ -0,0 +1,841 @@
+use std::{fmt, slice};
+use std::ops::Index;
+
+use heap::Heap;
+use list::{IndexOutOfRange, Sequence};
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+
+/// An associative `combine` with `empty` as its identity.
+pub trait Monoid: Clone {
+    fn empty() -> Self;
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// An element that a finger tree can summarise.
+///
+/// Every node caches the combined measure of the elements below it,
+/// which is what lets a finger tree be searched and split in O(log n).
+pub trait Measured {
+    type Measure: Monoid;
+
+    fn measure(&self) -> Self::Measure;
+}
+
+/// Counts elements, for finger trees used as sequences.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(pub usize);
+
+impl Monoid for Size {
+    fn empty() -> Self {
+        Size(0)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Size(self.0 + other.0)
+    }
+}
+
+/// An element of a finger tree used as a sequence, measured by `Size`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Element<E>(pub E);
+
+impl<E> Measured for Element<E> {
+    type Measure = Size;
+
+    fn measure(&self) -> Size {
+        Size(1)
+    }
+}
+
+/// The highest priority among some elements, for finger trees used as
+/// heaps. As with `Heap`, smaller elements have higher priority.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaxPriority<E>(pub Option<E>);
+
+impl<E: Clone + PartialOrd> Monoid for MaxPriority<E> {
+    fn empty() -> Self {
+        MaxPriority(None)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (None, _) => other.clone(),
+            (Some(x), Some(y)) if y < x => other.clone(),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// An element of a finger tree used as a heap, measured by
+/// `MaxPriority`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Prioritised<E>(pub E);
+
+impl<E: Clone + PartialOrd> Measured for Prioritised<E> {
+    type Measure = MaxPriority<E>;
+
+    fn measure(&self) -> MaxPriority<E> {
+        MaxPriority(Some(self.0.clone()))
+    }
+}
+
+type NodePointer<T, P> = SharedPointer<Node<T, P>, P>;
+
+/// Between one and four nodes at either end of a level.
+type Digit<T, P> = Vec<NodePointer<T, P>>;
+
+/// Nodes at every level of the tree share one type: a level `n` node is
+/// a `Branch` of two or three level `n - 1` nodes above the `Leaf`s.
+/// That stands in for the nested node types of the original, which
+/// Rust can't express without polymorphic recursion.
+enum Node<T: Measured, P: SharedPointerKind> {
+    Leaf(T),
+    Branch(T::Measure, Vec<NodePointer<T, P>>),
+}
+
+impl<T: Measured, P: SharedPointerKind> Node<T, P> {
+    fn measure(&self) -> T::Measure {
+        match *self {
+            Node::Leaf(ref x) => x.measure(),
+            Node::Branch(ref measure, _) => measure.clone(),
+        }
+    }
+
+    fn measure_all(nodes: &[NodePointer<T, P>]) -> T::Measure {
+        nodes.iter().fold(T::Measure::empty(), |measure, node| measure.combine(&node.measure()))
+    }
+
+    fn branch(children: Vec<NodePointer<T, P>>) -> NodePointer<T, P> {
+        P::new(Node::Branch(Node::<T, P>::measure_all(&children), children))
+    }
+
+    fn value(&self) -> &T {
+        match *self {
+            Node::Leaf(ref x) => x,
+            Node::Branch(..) => unreachable!("the top level only holds leaves"),
+        }
+    }
+
+    fn children(&self) -> &[NodePointer<T, P>] {
+        match *self {
+            Node::Branch(_, ref children) => children,
+            Node::Leaf(_) => unreachable!("the lower levels only hold branches"),
+        }
+    }
+
+    /// Descends through `nodes` to the first leaf where `pred` becomes
+    /// true, `acc` being the measure of everything to their left.
+    fn find_in<'a, F>(nodes: &'a [NodePointer<T, P>], pred: &F, acc: T::Measure) -> &'a T
+    where F: Fn(&T::Measure) -> bool,
+          T: 'a,
+          P: 'a,
+    {
+        let (mut nodes, mut acc) = (nodes, acc);
+        loop {
+            let mut i = 0;
+            while i + 1 < nodes.len() {
+                let next = acc.combine(&nodes[i].measure());
+                if pred(&next) {
+                    break;
+                }
+                acc = next;
+                i += 1;
+            }
+            match *nodes[i] {
+                Node::Leaf(ref x) => return x,
+                Node::Branch(_, ref children) => nodes = children,
+            }
+        }
+    }
+}
+
+enum Tree<T: Measured, P: SharedPointerKind> {
+    Empty,
+    Single(NodePointer<T, P>),
+    Deep(T::Measure, Digit<T, P>, SharedPointer<Tree<T, P>, P>, Digit<T, P>),
+}
+
+impl<T: Measured, P: SharedPointerKind> Clone for Tree<T, P> {
+    fn clone(&self) -> Self {
+        match *self {
+            Tree::Empty => Tree::Empty,
+            Tree::Single(ref x) => Tree::Single(x.clone()),
+            Tree::Deep(ref measure, ref prefix, ref middle, ref suffix) => {
+                Tree::Deep(measure.clone(), prefix.clone(), middle.clone(), suffix.clone())
+            },
+        }
+    }
+}
+
+impl<T: Measured, P: SharedPointerKind> Tree<T, P> {
+    fn measure(&self) -> T::Measure {
+        match *self {
+            Tree::Empty => T::Measure::empty(),
+            Tree::Single(ref x) => x.measure(),
+            Tree::Deep(ref measure, ..) => measure.clone(),
+        }
+    }
+
+    fn deep(prefix: Digit<T, P>, middle: Self, suffix: Digit<T, P>) -> Self {
+        let measure = Node::<T, P>::measure_all(&prefix)
+            .combine(&middle.measure())
+            .combine(&Node::<T, P>::measure_all(&suffix));
+        Tree::Deep(measure, prefix, P::new(middle), suffix)
+    }
+
+    fn from_digit(digit: &[NodePointer<T, P>]) -> Self {
+        digit.iter().fold(Tree::Empty, |tree, node| tree.push_back(node.clone()))
+    }
+
+    fn push_front(&self, node: NodePointer<T, P>) -> Self {
+        match *self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(ref x) => Self::deep(vec![node], Tree::Empty, vec![x.clone()]),
+            Tree::Deep(ref measure, ref prefix, ref middle, ref suffix) => {
+                let measure = node.measure().combine(measure);
+                if prefix.len() == 4 {
+                    let middle = middle.push_front(Node::<T, P>::branch(prefix[1..].to_vec()));
+                    Tree::Deep(measure, vec![node, prefix[0].clone()], P::new(middle), suffix.clone())
+                } else {
+                    let prefix = Some(node).into_iter().chain(prefix.iter().cloned()).collect();
+                    Tree::Deep(measure, prefix, middle.clone(), suffix.clone())
+                }
+            },
+        }
+    }
+
+    fn push_back(&self, node: NodePointer<T, P>) -> Self {
+        match *self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(ref x) => Self::deep(vec![x.clone()], Tree::Empty, vec![node]),
+            Tree::Deep(ref measure, ref prefix, ref middle, ref suffix) => {
+                let measure = measure.combine(&node.measure());
+                if suffix.len() == 4 {
+                    let middle = middle.push_back(Node::<T, P>::branch(suffix[..3].to_vec()));
+                    Tree::Deep(measure, prefix.clone(), P::new(middle), vec![suffix[3].clone(), node])
+                } else {
+                    let mut suffix = suffix.clone();
+                    suffix.push(node);
+                    Tree::Deep(measure, prefix.clone(), middle.clone(), suffix)
+                }
+            },
+        }
+    }
+
+    fn view_front(&self) -> Option<(NodePointer<T, P>, Self)> {
+        match *self {
+            Tree::Empty => None,
+            Tree::Single(ref x) => Some((x.clone(), Tree::Empty)),
+            Tree::Deep(_, ref prefix, ref middle, ref suffix) => {
+                let rest = Self::deep_front(prefix[1..].to_vec(), middle, suffix.clone());
+                Some((prefix[0].clone(), rest))
+            },
+        }
+    }
+
+    fn view_back(&self) -> Option<(Self, NodePointer<T, P>)> {
+        match *self {
+            Tree::Empty => None,
+            Tree::Single(ref x) => Some((Tree::Empty, x.clone())),
+            Tree::Deep(_, ref prefix, ref middle, ref suffix) => {
+                let (last, suffix) = suffix.split_last().expect("digits are never empty");
+                let rest = Self::deep_back(prefix.clone(), middle, suffix.to_vec());
+                Some((rest, last.clone()))
+            },
+        }
+    }
+
+    /// Like `deep`, but refills an empty prefix from the middle.
+    fn deep_front(prefix: Digit<T, P>, middle: &Self, suffix: Digit<T, P>) -> Self {
+        if !prefix.is_empty() {
+            return Self::deep(prefix, middle.clone(), suffix);
+        }
+        match middle.view_front() {
+            None => Self::from_digit(&suffix),
+            Some((node, middle)) => Self::deep(node.children().to_vec(), middle, suffix),
+        }
+    }
+
+    /// Like `deep`, but refills an empty suffix from the middle.
+    fn deep_back(prefix: Digit<T, P>, middle: &Self, suffix: Digit<T, P>) -> Self {
+        if !suffix.is_empty() {
+            return Self::deep(prefix, middle.clone(), suffix);
+        }
+        match middle.view_back() {
+            None => Self::from_digit(&prefix),
+            Some((middle, node)) => Self::deep(prefix, middle, node.children().to_vec()),
+        }
+    }
+
+    /// Joins two trees with some loose nodes of the same level between
+    /// them, recursing once per level of the shallower tree.
+    fn append(left: &Self, between: Vec<NodePointer<T, P>>, right: &Self) -> Self {
+        match (left, right) {
+            (Tree::Empty, _) => between.into_iter().rev().fold(right.clone(), |tree, node| tree.push_front(node)),
+            (_, Tree::Empty) => between.into_iter().fold(left.clone(), |tree, node| tree.push_back(node)),
+            (Tree::Single(x), _) => Self::append(&Tree::Empty, between, right).push_front(x.clone()),
+            (_, Tree::Single(x)) => Self::append(left, between, &Tree::Empty).push_back(x.clone()),
+            (Tree::Deep(_, prefix, m1, s1), Tree::Deep(_, p2, m2, suffix)) => {
+                let mut nodes = s1.clone();
+                nodes.extend(between);
+                nodes.extend(p2.iter().cloned());
+                Self::deep(prefix.clone(), Self::append(m1, Self::group(nodes), m2), suffix.clone())
+            },
+        }
+    }
+
+    /// Packs between 2 and 12 nodes into branches of two or three.
+    fn group(nodes: Vec<NodePointer<T, P>>) -> Vec<NodePointer<T, P>> {
+        let mut branches = Vec::new();
+        let mut rest = &nodes[..];
+        loop {
+            match rest.len() {
+                2 | 3 => {
+                    branches.push(Node::<T, P>::branch(rest.to_vec()));
+                    return branches;
+                },
+                4 => {
+                    branches.push(Node::<T, P>::branch(rest[..2].to_vec()));
+                    branches.push(Node::<T, P>::branch(rest[2..].to_vec()));
+                    return branches;
+                },
+                _ => {
+                    branches.push(Node::<T, P>::branch(rest[..3].to_vec()));
+                    rest = &rest[3..];
+                },
+            }
+        }
+    }
+
+    fn split_digit<F>(pred: &F, acc: T::Measure, digit: &[NodePointer<T, P>])
+                      -> (Digit<T, P>, NodePointer<T, P>, Digit<T, P>)
+    where F: Fn(&T::Measure) -> bool,
+    {
+        let mut acc = acc;
+        let mut i = 0;
+        while i + 1 < digit.len() {
+            acc = acc.combine(&digit[i].measure());
+            if pred(&acc) {
+                break;
+            }
+            i += 1;
+        }
+        (digit[..i].to_vec(), digit[i].clone(), digit[i + 1..].to_vec())
+    }
+
+    /// Splits a non-empty tree around the node where `pred` first
+    /// holds, `acc` being the measure of everything to its left.
+    fn split_tree<F>(&self, pred: &F, acc: T::Measure) -> (Self, NodePointer<T, P>, Self)
+    where F: Fn(&T::Measure) -> bool,
+    {
+        match *self {
+            Tree::Empty => unreachable!("only non-empty trees are split"),
+            Tree::Single(ref x) => (Tree::Empty, x.clone(), Tree::Empty),
+            Tree::Deep(_, ref prefix, ref middle, ref suffix) => {
+                let up_to_prefix = acc.combine(&Node::<T, P>::measure_all(prefix));
+                if pred(&up_to_prefix) {
+                    let (l, x, r) = Self::split_digit(pred, acc, prefix);
+                    return (Self::from_digit(&l), x, Self::deep_front(r, middle, suffix.clone()));
+                }
+                let up_to_middle = up_to_prefix.combine(&middle.measure());
+                if pred(&up_to_middle) {
+                    let (ml, node, mr) = middle.split_tree(pred, up_to_prefix.clone());
+                    let acc = up_to_prefix.combine(&ml.measure());
+                    let (l, x, r) = Self::split_digit(pred, acc, node.children());
+                    return (Self::deep_back(prefix.clone(), &ml, l), x, Self::deep_front(r, &mr, suffix.clone()));
+                }
+                let (l, x, r) = Self::split_digit(pred, up_to_middle, suffix);
+                (Self::deep_back(prefix.clone(), middle, l), x, Self::from_digit(&r))
+            },
+        }
+    }
+}
+
+/// Hinze and Paterson's 2-3 finger tree.
+///
+/// The ends of the tree are kept within a few nodes of the root, so
+/// both can be reached in amortized O(1), while the cached measures let
+/// `split` and `find` go straight to the element where a predicate on
+/// the measure flips from false to true. The element type decides what
+/// the tree can do: `Element` makes it a `Sequence` indexed by
+/// position and `Prioritised` makes it a `Heap`.
+pub struct FingerTree<T: Measured, P: SharedPointerKind = RcK>(Tree<T, P>);
+
+impl<T: Measured> FingerTree<T> {
+    pub fn new() -> Self {
+        FingerTree(Tree::Empty)
+    }
+}
+
+impl<T: Measured> FingerTree<T, ArcK> {
+    pub fn new_sync() -> Self {
+        FingerTree(Tree::Empty)
+    }
+}
+
+impl<T: Measured, P: SharedPointerKind> FingerTree<T, P> {
+    pub fn is_empty(&self) -> bool {
+        matches!(self.0, Tree::Empty)
+    }
+
+    /// The combined measure of every element, in O(1).
+    pub fn measure(&self) -> T::Measure {
+        self.0.measure()
+    }
+
+    pub fn push_front(&self, x: T) -> Self {
+        FingerTree(self.0.push_front(P::new(Node::Leaf(x))))
+    }
+
+    pub fn push_back(&self, x: T) -> Self {
+        FingerTree(self.0.push_back(P::new(Node::Leaf(x))))
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        match self.0 {
+            Tree::Empty => None,
+            Tree::Single(ref x) => Some(x.value()),
+            Tree::Deep(_, ref prefix, _, _) => Some(prefix[0].value()),
+        }
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        match self.0 {
+            Tree::Empty => None,
+            Tree::Single(ref x) => Some(x.value()),
+            Tree::Deep(_, _, _, ref suffix) => suffix.last().map(|x| x.value()),
+        }
+    }
+
+    /// Splits off the first element.
+    pub fn view_front(&self) -> Option<(&T, Self)> {
+        let rest = self.0.view_front()?.1;
+        self.front().map(|x| (x, FingerTree(rest)))
+    }
+
+    /// Splits off the last element.
+    pub fn view_back(&self) -> Option<(Self, &T)> {
+        let rest = self.0.view_back()?.0;
+        self.back().map(|x| (FingerTree(rest), x))
+    }
+
+    /// Joins two trees in O(log n) of the smaller one.
+    pub fn concat(&self, other: &Self) -> Self {
+        FingerTree(Tree::append(&self.0, Vec::new(), &other.0))
+    }
+
+    /// Splits the tree before the first element where `pred` holds for
+    /// the measure of it and everything to its left.
+    ///
+    /// `pred` has to be monotonic, false up to some point and true from
+    /// there on. If it never holds the whole tree ends up on the left.
+    pub fn split<F>(&self, pred: F) -> (Self, Self)
+    where F: Fn(&T::Measure) -> bool,
+    {
+        if self.is_empty() || !pred(&self.measure()) {
+            return (self.clone(), FingerTree(Tree::Empty));
+        }
+        let (left, x, right) = self.0.split_tree(&pred, T::Measure::empty());
+        (FingerTree(left), FingerTree(right.push_front(x)))
+    }
+
+    /// Finds the element `split` would split before, without building
+    /// either side.
+    pub fn find<F>(&self, pred: F) -> Option<&T>
+    where F: Fn(&T::Measure) -> bool,
+    {
+        if self.is_empty() || !pred(&self.measure()) {
+            return None;
+        }
+        let mut acc = T::Measure::empty();
+        let mut tree = &self.0;
+        loop {
+            match *tree {
+                Tree::Empty => return None,
+                Tree::Single(ref x) => return Some(Node::<T, P>::find_in(slice::from_ref(x), &pred, acc)),
+                Tree::Deep(_, ref prefix, ref middle, ref suffix) => {
+                    let up_to_prefix = acc.combine(&Node::<T, P>::measure_all(prefix));
+                    if pred(&up_to_prefix) {
+                        return Some(Node::<T, P>::find_in(prefix, &pred, acc));
+                    }
+                    let up_to_middle = up_to_prefix.combine(&middle.measure());
+                    if !pred(&up_to_middle) {
+                        return Some(Node::<T, P>::find_in(suffix, &pred, up_to_middle));
+                    }
+                    acc = up_to_prefix;
+                    tree = middle;
+                },
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { pending: vec![Pending::Tree(&self.0)] }
+    }
+}
+
+impl<T: Measured, P: SharedPointerKind> Clone for FingerTree<T, P> {
+    fn clone(&self) -> Self {
+        FingerTree(self.0.clone())
+    }
+}
+
+enum Pending<'a, T: Measured + 'a, P: SharedPointerKind + 'a> {
+    Tree(&'a Tree<T, P>),
+    Node(&'a Node<T, P>),
+}
+
+pub struct Iter<'a, T: Measured + 'a, P: SharedPointerKind + 'a> {
+    pending: Vec<Pending<'a, T, P>>,
+}
+
+impl<'a, T: Measured, P: SharedPointerKind> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match self.pending.pop()? {
+                Pending::Node(Node::Leaf(x)) => return Some(x),
+                Pending::Node(Node::Branch(_, children)) => {
+                    self.pending.extend(children.iter().rev().map(|node| Pending::Node(node)));
+                },
+                Pending::Tree(Tree::Empty) => {},
+                Pending::Tree(Tree::Single(x)) => self.pending.push(Pending::Node(x)),
+                Pending::Tree(Tree::Deep(_, prefix, middle, suffix)) => {
+                    self.pending.extend(suffix.iter().rev().map(|node| Pending::Node(node)));
+                    self.pending.push(Pending::Tree(middle));
+                    self.pending.extend(prefix.iter().rev().map(|node| Pending::Node(node)));
+                },
+            }
+        }
+    }
+}
+
+impl<T: Measured, P: SharedPointerKind> Default for FingerTree<T, P> {
+    fn default() -> Self {
+        FingerTree(Tree::Empty)
+    }
+}
+
+impl<T, P> fmt::Debug for FingerTree<T, P>
+where T: Measured + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, P> PartialEq for FingerTree<T, P>
+where T: Measured + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+/// Borrows the elements of a finger tree used as a sequence.
+pub struct Elements<'a, E: 'a, P: SharedPointerKind + 'a>(Iter<'a, Element<E>, P>);
+
+impl<'a, E, P: SharedPointerKind> Iterator for Elements<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.0.next().map(|x| &x.0)
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for FingerTree<Element<E>, P> {
+    type Iter<'a> = Elements<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        FingerTree(Tree::Empty)
+    }
+
+    fn is_empty(&self) -> bool {
+        FingerTree::is_empty(self)
+    }
+
+    fn cons(&self, el: E) -> Self {
+        self.push_front(Element(el))
+    }
+
+    fn first(&self) -> Option<&E> {
+        self.front().map(|x| &x.0)
+    }
+
+    fn rest(&self) -> Self {
+        match self.view_front() {
+            None => self.clone(),
+            Some((_, rest)) => rest,
+        }
+    }
+
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let (left, right) = self.split(|size| size.0 > index);
+        match right.view_front() {
+            None => Err(IndexOutOfRange { index, len: self.len() }),
+            Some((_, rest)) => Ok(left.concat(&rest.push_front(Element(val)))),
+        }
+    }
+
+    fn split_at(&self, index: usize) -> Result<(Self, Self), IndexOutOfRange> {
+        if index > self.len() {
+            return Err(IndexOutOfRange { index, len: self.len() });
+        }
+        Ok(self.split(|size| size.0 > index))
+    }
+
+    fn insert_at(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let (left, right) = self.split_at(index)?;
+        Ok(left.push_back(Element(val)).concat(&right))
+    }
+
+    fn remove_at(&self, index: usize) -> Result<Self, IndexOutOfRange> {
+        let (left, right) = self.split(|size| size.0 > index);
+        match right.view_front() {
+            None => Err(IndexOutOfRange { index, len: self.len() }),
+            Some((_, rest)) => Ok(left.concat(&rest)),
+        }
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        FingerTree::concat(self, other)
+    }
+
+    fn iter(&self) -> Elements<'_, E, P> {
+        Elements(FingerTree::iter(self))
+    }
+
+    fn len(&self) -> usize {
+        self.measure().0
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.find(|size| size.0 > index).map(|x| &x.0)
+    }
+
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        self.nth(index).ok_or(IndexOutOfRange { index, len: self.len() })
+    }
+
+    fn last(&self) -> Option<&E> {
+        self.back().map(|x| &x.0)
+    }
+
+    fn take(&self, n: usize) -> Self {
+        self.split(|size| size.0 > n).0
+    }
+
+    fn drop(&self, n: usize) -> Self {
+        self.split(|size| size.0 > n).1
+    }
+}
+
+impl<E, P> Index<usize> for FingerTree<Element<E>, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    type Output = E;
+
+    fn index(&self, index: usize) -> &E {
+        match self.nth(index) {
+            Some(x) => x,
+            None => panic!("index out of bounds: the len is {} but the index is {}", self.len(), index),
+        }
+    }
+}
+
+impl<E, P> Heap for FingerTree<Prioritised<E>, P>
+where E: Clone + PartialOrd,
+      P: SharedPointerKind,
+{
+    type Item = E;
+
+    fn empty() -> Self {
+        FingerTree(Tree::Empty)
+    }
+
+    fn is_empty(&self) -> bool {
+        FingerTree::is_empty(self)
+    }
+
+    fn insert(&self, x: E) -> Self {
+        self.push_back(Prioritised(x))
+    }
+
+    fn merge(&self, other: &Self) -> Self {
+        self.concat(other)
+    }
+
+    fn find_min(&self) -> Option<&E> {
+        let min = self.measure().0?;
+        self.find(|p| matches!(p.0, Some(ref x) if *x <= min)).map(|x| &x.0)
+    }
+
+    fn delete_min(&self) -> Self {
+        let min = match self.measure().0 {
+            None => return self.clone(),
+            Some(min) => min,
+        };
+        let (left, right) = self.split(|p| matches!(p.0, Some(ref x) if *x <= min));
+        match right.view_front() {
+            None => self.clone(),
+            Some((_, rest)) => left.concat(&rest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type FingerList<E> = FingerTree<Element<E>>;
+
+    fn finger_list_of(n: usize) -> FingerList<usize> {
+        (0..n).fold(FingerTree::new(), |t, x| t.push_back(Element(x)))
+    }
+
+    fn elements(t: &FingerList<usize>) -> Vec<usize> {
+        Sequence::iter(t).cloned().collect()
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let t: FingerList<u8> = FingerTree::new();
+        assert_eq!(t.first(), None);
+        assert!(t.rest().is_empty());
+        assert!(t.view_back().is_none());
+    }
+
+    #[test]
+    fn both_ends_are_reachable() {
+        let t = finger_list_of(100);
+        assert_eq!(Some(&Element(0)), t.front());
+        assert_eq!(Some(&Element(99)), t.back());
+
+        let (rest, last) = t.view_back().unwrap();
+        assert_eq!(&Element(99), last);
+        assert_eq!(Some(&Element(98)), rest.back());
+        assert_eq!(99, rest.len());
+    }
+
+    #[test]
+    fn rest_walks_every_element() {
+        let mut t = finger_list_of(200).cons(1000);
+        assert_eq!(Some(&1000), t.first());
+        t = t.rest();
+        for i in 0..200 {
+            assert_eq!(Some(&i), t.first());
+            assert_eq!(200 - i, t.len());
+            t = t.rest();
+        }
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn nth_every_index() {
+        let t = finger_list_of(500);
+        for i in 0..500 {
+            assert_eq!(Some(&i), t.nth(i));
+            assert_eq!(i, t[i]);
+        }
+        assert_eq!(None, t.nth(500));
+        assert_eq!((0..500).collect::<Vec<_>>(), elements(&t));
+    }
+
+    #[test]
+    fn split_at_every_index() {
+        let t = finger_list_of(100);
+        for i in 0..=100 {
+            let (left, right) = t.split(|size| size.0 > i);
+            assert_eq!((0..i).collect::<Vec<_>>(), elements(&left));
+            assert_eq!((i..100).collect::<Vec<_>>(), elements(&right));
+            assert_eq!(elements(&t.take(i)), elements(&left));
+            assert_eq!(elements(&t.drop(i)), elements(&right));
+        }
+    }
+
+    #[test]
+    fn concat_joins_trees_of_any_size() {
+        for n in 0..40 {
+            for m in [0, 1, 5, 17, 60] {
+                let left = finger_list_of(n);
+                let right = (0..m).fold(FingerTree::new(), |t, x| t.push_back(Element(n + x)));
+                let joined = left.concat(&right);
+                assert_eq!((0..n + m).collect::<Vec<_>>(), elements(&joined));
+                assert_eq!(n + m, joined.len());
+            }
+        }
+    }
+
+    #[test]
+    fn concat_leaves_both_trees_usable() {
+        let xs = finger_list_of(3);
+        let ys = finger_list_of(2);
+        let _zs = ys.concat(&xs);
+        assert_eq!(vec![0, 1, 2], elements(&xs));
+        assert_eq!(vec![0, 1], elements(&ys));
+    }
+
+    #[test]
+    fn update_leaves_original_alone() {
+        let t = finger_list_of(50);
+        for i in 0..50 {
+            let new_t = t.update(i, 100).unwrap();
+            assert_eq!(Some(&100), new_t.nth(i));
+            assert_eq!(Some(&i), t.nth(i));
+            assert_eq!(50, new_t.len());
+        }
+        assert_eq!(Err(IndexOutOfRange { index: 50, len: 50 }), t.update(50, 100));
+    }
+
+    #[test]
+    fn insert_and_remove_at() {
+        let t = finger_list_of(100);
+        let inserted = t.insert_at(40, 1000).unwrap();
+        assert_eq!(101, inserted.len());
+        assert_eq!(Some(&1000), inserted.nth(40));
+        assert_eq!(Some(&40), inserted.nth(41));
+        assert_eq!(elements(&t), elements(&inserted.remove_at(40).unwrap()));
+
+        assert_eq!(Err(IndexOutOfRange { index: 101, len: 100 }), t.split_at(101));
+        assert_eq!(Err(IndexOutOfRange { index: 100, len: 100 }), t.remove_at(100));
+        assert_eq!(Ok(&99), t.get(99));
+    }
+
+    fn heap_of(xs: &[i32]) -> FingerTree<Prioritised<i32>> {
+        xs.iter().fold(Heap::empty(), |h: FingerTree<_>, &x| h.insert(x))
+    }
+
+    #[test]
+    fn find_min_finds_smallest() {
+        let h = heap_of(&[5, 3, 8, 1, 9, 2]);
+        assert_eq!(Some(&1), h.find_min());
+        assert_eq!(Some(&2), h.delete_min().find_min());
+        assert_eq!(Some(&1), h.find_min());
+    }
+
+    #[test]
+    fn merged_heaps_delete_in_order() {
+        let mut h = heap_of(&[7, 0, 4]).merge(&heap_of(&[6, 4, 1]));
+        let mut sorted = Vec::new();
+        while let Some(&x) = h.find_min() {
+            sorted.push(x);
+            h = h.delete_min();
+        }
+        assert_eq!(vec![0, 1, 4, 4, 6, 7], sorted);
+        assert!(Heap::is_empty(&h));
+    }
+
+    #[test]
+    fn large_tree() {
+        let t = finger_list_of(100_000);
+        assert_eq!(Some(&76_543), t.nth(76_543));
+        let (left, right) = t.split(|size| size.0 > 50_000);
+        let t = right.concat(&left);
+        assert_eq!(Some(&0), t.nth(50_000));
+        assert_eq!(100_000, t.len());
+    }
+}
@@ -1,5 +1,8 @@
-use std::rc::Rc;
-use tree::Tree;
+use std::fmt;
+
+use shared::{RcK, SharedPointer, SharedPointerKind};
+use sharing::{allocation_bytes, SharedNodes};
+use tree::{self, Tree};
 
 pub trait Heap {
     type Item;
@@ -14,35 +17,70 @@ pub trait Heap {
     fn delete_min(&self) -> Self;
 }
 
-type HeapTree<T> = Rc<Tree<(usize, T)>>;
+/// Builds a `LeftistHeap` from the given elements.
+///
+/// ```
+/// # #[macro_use] extern crate functional_data_structures;
+/// # use functional_data_structures::prelude::*;
+/// # fn main() {
+/// let h = heap![3, 1, 2];
+/// assert_eq!(Some(&1), h.find_min());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! heap {
+    ($($x:expr),* $(,)?) => {{
+        let heap = <$crate::LeftistHeap<_> as $crate::Heap>::empty();
+        $(let heap = $crate::Heap::insert(&heap, $x);)*
+        heap
+    }};
+}
+
+type HeapTree<T, P> = SharedPointer<Tree<(usize, T), P>, P>;
+
+pub struct LeftistHeap<T, P: SharedPointerKind = RcK>(HeapTree<T, P>);
 
-#[derive(Clone, Debug)]
-pub struct LeftistHeap<T>(HeapTree<T>);
+impl<T, P: SharedPointerKind> Clone for LeftistHeap<T, P> {
+    fn clone(&self) -> Self {
+        LeftistHeap(self.0.clone())
+    }
+}
 
-fn rank<T>(node: &HeapTree<T>) -> usize {
+impl<T, P> fmt::Debug for LeftistHeap<T, P>
+where T: fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("LeftistHeap").field(&*self.0).finish()
+    }
+}
+
+fn rank<T, P: SharedPointerKind>(node: &HeapTree<T, P>) -> usize {
     match **node {
         Tree::E => 0,
         Tree::T(_, (r, _), _) => r,
     }
 }
 
-fn make_t<T>(x: &T, a: &HeapTree<T>, b: &HeapTree<T>) -> HeapTree<T>
-where T: Clone
+fn make_t<T, P>(x: &T, a: &HeapTree<T, P>, b: &HeapTree<T, P>) -> HeapTree<T, P>
+where T: Clone,
+      P: SharedPointerKind,
 {
-    if rank(a) >= rank(b) {
-        Tree::node(a, (rank(b)+1, (*x).clone()), b)
+    if rank::<T, P>(a) >= rank::<T, P>(b) {
+        Tree::<_, P>::node(a, (rank::<T, P>(b)+1, (*x).clone()), b)
     } else {
-        Tree::node(b, (rank(a)+1, (*x).clone()), a)
+        Tree::<_, P>::node(b, (rank::<T, P>(a)+1, (*x).clone()), a)
     }
 }
 
-impl<T> Heap for LeftistHeap<T>
+impl<T, P> Heap for LeftistHeap<T, P>
 where T: Clone + PartialOrd,
+      P: SharedPointerKind,
 {
     type Item = T;
 
     fn empty() -> Self {
-        LeftistHeap(Tree::<(usize, T)>::empty())
+        LeftistHeap(Tree::<(usize, T), P>::empty())
     }
 
     fn is_empty(&self) -> bool {
@@ -53,27 +91,28 @@ where T: Clone + PartialOrd,
     }
 
     fn insert(&self, x: Self::Item) -> Self {
-        self.merge(&LeftistHeap(Tree::leaf((1, x))))
+        self.merge(&LeftistHeap(Tree::<_, P>::leaf((1, x))))
     }
 
     fn merge(&self, other: &Self) -> Self {
-        fn iter<T>(h1: &HeapTree<T>, h2: &HeapTree<T>) -> HeapTree<T>
+        fn iter<T, P>(h1: &HeapTree<T, P>, h2: &HeapTree<T, P>) -> HeapTree<T, P>
         where T: Clone + PartialOrd,
+              P: SharedPointerKind,
         {
-            match (h1.as_ref(), h2.as_ref()) {
-                (Tree::E, _) => Rc::clone(&h2),
-                (_, Tree::E) => Rc::clone(&h1),
+            match (&**h1, &**h2) {
+                (Tree::E, _) => h2.clone(),
+                (_, Tree::E) => h1.clone(),
                 (Tree::T(ref a1, (_, ref x), ref b1),
                  Tree::T(ref a2, (_, ref y), ref b2)) => {
                     if *x <= *y {
-                        make_t(x, a1, &iter(b1, h2))
+                        make_t::<T, P>(x, a1, &iter::<T, P>(b1, h2))
                     } else {
-                        make_t(y, a2, &iter(h1, b2))
+                        make_t::<T, P>(y, a2, &iter::<T, P>(h1, b2))
                     }
                 }
             }
         }
-        LeftistHeap(iter(&self.0, &other.0))
+        LeftistHeap(iter::<T, P>(&self.0, &other.0))
     }
 
     fn find_min(&self) -> Option<&Self::Item> {
@@ -86,16 +125,29 @@ where T: Clone + PartialOrd,
     fn delete_min(&self) -> Self {
         match *self.0 {
             Tree::E => self.clone(),
-            Tree::T(ref a, _, ref b) => LeftistHeap(Rc::clone(&a)).merge(&LeftistHeap(Rc::clone(b))),
+            Tree::T(ref a, _, ref b) => LeftistHeap(a.clone()).merge(&LeftistHeap(b.clone())),
         }
     }
 }
 
+impl<T, P: SharedPointerKind> SharedNodes for LeftistHeap<T, P> {
+    fn node_bytes() -> usize {
+        allocation_bytes::<Tree<(usize, T), P>>()
+    }
+
+    fn visit_nodes<F>(&self, visit: F)
+    where F: FnMut(usize) -> bool,
+    {
+        tree::visit_tree_nodes::<(usize, T), P, F>(&self.0, visit)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use tree::BinaryTree;
+    use shared::ArcK;
+    use sharing::SharingReport;
 
     #[test]
     fn empty_leftist_heap() {
@@ -126,4 +178,42 @@ mod tests {
         let h1 = h.delete_min();
         assert_eq!(Some(&7), h1.find_min());
     }
+
+    #[test]
+    fn heap_macro() {
+        let h = heap![5, 3, 7];
+        assert_eq!(Some(&3), h.find_min());
+        assert_eq!(Some(&5), h.delete_min().find_min());
+    }
+
+    #[test]
+    fn sharing_between_heap_versions() {
+        let h = heap![5, 7, 9];
+        let h2 = h.insert(1);
+
+        let report = SharingReport::of(&[h.clone(), h2]);
+        assert!(report.shared_nodes > 0);
+        assert_eq!(report.reachable_nodes[0],
+                   report.shared_nodes + report.unique_nodes[0]);
+        assert_eq!(report.reachable_nodes[1],
+                   report.shared_nodes + report.unique_nodes[1]);
+
+        let report = SharingReport::of(&[h.clone(), h]);
+        assert_eq!(vec![0, 0], report.unique_nodes);
+    }
+
+    #[test]
+    fn sync_heap_is_shared_across_threads() {
+        use std::thread;
+
+        let h = LeftistHeap::<u8, ArcK>::empty()
+            .insert(5)
+            .insert(7);
+        let h2 = h.clone();
+        let handle = thread::spawn(move || h2.delete_min().insert(6));
+
+        let h3 = handle.join().unwrap();
+        assert_eq!(Some(&5), h.find_min());
+        assert_eq!(Some(&6), h3.find_min());
+    }
 }
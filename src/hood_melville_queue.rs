@@ -0,0 +1,424 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::vec;
+
+use list::{self, PersistentList, Sequence};
+use queue::Queue;
+use shared::{ArcK, RcK, SharedPointerKind};
+
+/// How far a Hood–Melville queue has got with moving its rear onto its
+/// front.
+enum Rotation<E, P: SharedPointerKind> {
+    Idle,
+    /// Reversing the old front and the old rear side by side. `valid`
+    /// counts the elements of `reversed_front` that haven't been taken
+    /// off the queue since the rotation began.
+    Reversing {
+        valid: usize,
+        front: PersistentList<E, P>,
+        reversed_front: PersistentList<E, P>,
+        rear: PersistentList<E, P>,
+        reversed_rear: PersistentList<E, P>,
+    },
+    /// Consing the valid part of the reversed front back onto the
+    /// reversed rear, which becomes the new front.
+    Appending {
+        valid: usize,
+        reversed_front: PersistentList<E, P>,
+        new_front: PersistentList<E, P>,
+    },
+    Done(PersistentList<E, P>),
+}
+
+impl<E: Clone, P: SharedPointerKind> Rotation<E, P> {
+    /// Does one constant-time step of the rotation.
+    fn step(&self) -> Self {
+        match *self {
+            Rotation::Reversing { valid, ref front, ref reversed_front, ref rear, ref reversed_rear } => {
+                let y = rear.first().expect("a rotation's rear is longer than its front");
+                match front.first() {
+                    Some(x) => Rotation::Reversing {
+                        valid: valid + 1,
+                        front: front.rest(),
+                        reversed_front: reversed_front.cons(x.clone()),
+                        rear: rear.rest(),
+                        reversed_rear: reversed_rear.cons(y.clone()),
+                    },
+                    None => Rotation::Appending {
+                        valid,
+                        reversed_front: reversed_front.clone(),
+                        new_front: reversed_rear.cons(y.clone()),
+                    },
+                }
+            },
+            Rotation::Appending { valid: 0, ref new_front, .. } => Rotation::Done(new_front.clone()),
+            Rotation::Appending { valid, ref reversed_front, ref new_front } => {
+                let x = reversed_front.first().expect("valid elements are still to be appended");
+                Rotation::Appending {
+                    valid: valid - 1,
+                    reversed_front: reversed_front.rest(),
+                    new_front: new_front.cons(x.clone()),
+                }
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Accounts for the head of the queue being taken off while the
+    /// rotation is still copying it.
+    fn invalidate(&self) -> Self {
+        match *self {
+            Rotation::Reversing { valid, ref front, ref reversed_front, ref rear, ref reversed_rear } => {
+                Rotation::Reversing {
+                    valid: valid - 1,
+                    front: front.clone(),
+                    reversed_front: reversed_front.clone(),
+                    rear: rear.clone(),
+                    reversed_rear: reversed_rear.clone(),
+                }
+            },
+            Rotation::Appending { valid: 0, ref new_front, .. } => Rotation::Done(new_front.rest()),
+            Rotation::Appending { valid, ref reversed_front, ref new_front } => Rotation::Appending {
+                valid: valid - 1,
+                reversed_front: reversed_front.clone(),
+                new_front: new_front.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for Rotation<E, P> {
+    fn clone(&self) -> Self {
+        match *self {
+            Rotation::Idle => Rotation::Idle,
+            Rotation::Reversing { valid, ref front, ref reversed_front, ref rear, ref reversed_rear } => {
+                Rotation::Reversing {
+                    valid,
+                    front: front.clone(),
+                    reversed_front: reversed_front.clone(),
+                    rear: rear.clone(),
+                    reversed_rear: reversed_rear.clone(),
+                }
+            },
+            Rotation::Appending { valid, ref reversed_front, ref new_front } => Rotation::Appending {
+                valid,
+                reversed_front: reversed_front.clone(),
+                new_front: new_front.clone(),
+            },
+            Rotation::Done(ref new_front) => Rotation::Done(new_front.clone()),
+        }
+    }
+}
+
+/// The Hood–Melville queue, from section 8.2.1 of Okasaki.
+///
+/// Like the real-time queue it moves the rear onto the front a little
+/// at a time, but it keeps track of the rotation as explicit state
+/// rather than in suspensions: the old front and rear are reversed a
+/// step at a time, then the reversed front is appended to the reversed
+/// rear, and every operation advances the rotation by two steps. So
+/// `snoc`, `head` and `tail` are O(1) in the worst case, and each one
+/// allocates at most a fixed number of cells.
+pub struct HoodMelvilleQueue<E, P: SharedPointerKind = RcK> {
+    front_len: usize,
+    front: PersistentList<E, P>,
+    rotation: Rotation<E, P>,
+    rear_len: usize,
+    /// The back of the queue, last element first.
+    rear: PersistentList<E, P>,
+}
+
+impl<E: Clone> HoodMelvilleQueue<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone> HoodMelvilleQueue<E, ArcK> {
+    pub fn new_sync() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> HoodMelvilleQueue<E, P> {
+    /// Starts a rotation once the rear outgrows the front, and takes two
+    /// steps of whichever rotation is under way.
+    fn checked(front_len: usize,
+               front: PersistentList<E, P>,
+               rotation: Rotation<E, P>,
+               rear_len: usize,
+               rear: PersistentList<E, P>) -> Self {
+        let (front_len, rotation, rear_len, rear) = if rear_len <= front_len {
+            (front_len, rotation, rear_len, rear)
+        } else {
+            let rotation = Rotation::Reversing {
+                valid: 0,
+                front: front.clone(),
+                reversed_front: PersistentList::empty(),
+                rear,
+                reversed_rear: PersistentList::empty(),
+            };
+            (front_len + rear_len, rotation, 0, PersistentList::empty())
+        };
+
+        match rotation.step().step() {
+            Rotation::Done(new_front) => HoodMelvilleQueue {
+                front_len,
+                front: new_front,
+                rotation: Rotation::Idle,
+                rear_len,
+                rear,
+            },
+            rotation => HoodMelvilleQueue { front_len, front, rotation, rear_len, rear },
+        }
+    }
+
+    /// Borrows the elements from the front of the queue to the back.
+    pub fn iter(&self) -> Iter<'_, E, P> {
+        // While a rotation is under way the front only holds what is
+        // left of the old front; the old rear is inside the rotation.
+        let old_rear_len = self.front_len - self.front.len();
+        let rotating = match self.rotation {
+            Rotation::Reversing { ref rear, ref reversed_rear, .. } => {
+                let mut pending = rear.iter().collect::<Vec<_>>();
+                pending.reverse();
+                pending.extend(reversed_rear.iter());
+                pending
+            },
+            Rotation::Appending { ref new_front, .. } => {
+                let appended = new_front.len() - old_rear_len;
+                new_front.iter().skip(appended).collect()
+            },
+            Rotation::Idle | Rotation::Done(_) => Vec::new(),
+        };
+        Iter {
+            front: self.front.iter(),
+            rotating: rotating.into_iter(),
+            rear: self.rear.iter().collect(),
+        }
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for HoodMelvilleQueue<E, P> {
+    fn clone(&self) -> Self {
+        HoodMelvilleQueue {
+            front_len: self.front_len,
+            front: self.front.clone(),
+            rotation: self.rotation.clone(),
+            rear_len: self.rear_len,
+            rear: self.rear.clone(),
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Queue for HoodMelvilleQueue<E, P> {
+    type Item = E;
+
+    fn empty() -> Self {
+        HoodMelvilleQueue {
+            front_len: 0,
+            front: PersistentList::empty(),
+            rotation: Rotation::Idle,
+            rear_len: 0,
+            rear: PersistentList::empty(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    fn snoc(&self, x: E) -> Self {
+        Self::checked(self.front_len,
+                      self.front.clone(),
+                      self.rotation.clone(),
+                      self.rear_len + 1,
+                      self.rear.cons(x))
+    }
+
+    fn head(&self) -> Option<&E> {
+        self.front.first()
+    }
+
+    fn tail(&self) -> Self {
+        if self.is_empty() {
+            return self.clone();
+        }
+        Self::checked(self.front_len - 1,
+                      self.front.rest(),
+                      self.rotation.invalidate(),
+                      self.rear_len,
+                      self.rear.clone())
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    front: list::Iter<'a, E, P>,
+    /// The old rear, in order, while a rotation is moving it.
+    rotating: vec::IntoIter<&'a E>,
+    /// The back of the queue, last element first.
+    rear: Vec<&'a E>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.front.next()
+            .or_else(|| self.rotating.next())
+            .or_else(|| self.rear.pop())
+    }
+}
+
+/// Owning iterator over a `HoodMelvilleQueue`, which hands out the
+/// elements by taking the `tail` of the queue one step at a time.
+pub struct IntoIter<E, P: SharedPointerKind> {
+    queue: HoodMelvilleQueue<E, P>,
+}
+
+impl<E: Clone, P: SharedPointerKind> Iterator for IntoIter<E, P> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let x = self.queue.head()?.clone();
+        self.queue = self.queue.tail();
+        Some(x)
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for HoodMelvilleQueue<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for HoodMelvilleQueue<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for HoodMelvilleQueue<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.front_len + self.rear_len == other.front_len + other.rear_len
+            && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, E: Clone, P: SharedPointerKind> IntoIterator for &'a HoodMelvilleQueue<E, P> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E, P>;
+
+    fn into_iter(self) -> Iter<'a, E, P> {
+        self.iter()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> IntoIterator for HoodMelvilleQueue<E, P> {
+    type Item = E;
+    type IntoIter = IntoIter<E, P>;
+
+    fn into_iter(self) -> IntoIter<E, P> {
+        IntoIter { queue: self }
+    }
+}
+
+/// The first element of the iterator ends up at the head of the queue.
+impl<E: Clone, P: SharedPointerKind> FromIterator<E> for HoodMelvilleQueue<E, P> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        let front = iter.into_iter().collect::<PersistentList<_, P>>();
+        HoodMelvilleQueue {
+            front_len: front.len(),
+            front,
+            rotation: Rotation::Idle,
+            rear_len: 0,
+            rear: PersistentList::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::thread;
+
+    use queue::checks::{self, most_work_per_operation, Counted};
+
+    #[test]
+    fn behaves_as_a_fifo() {
+        checks::check_fifo::<HoodMelvilleQueue<usize>>();
+    }
+
+    #[test]
+    fn iter_matches_the_queue_mid_rotation() {
+        // Mix snocs and tails so that rotations get interrupted in both
+        // of their phases.
+        let mut q = HoodMelvilleQueue::new();
+        let mut expected = Vec::new();
+        let mut next = 0;
+        for round in 0..300 {
+            for _ in 0..round % 5 {
+                q = q.snoc(next);
+                expected.push(next);
+                next += 1;
+            }
+            for _ in 0..round % 3 {
+                q = q.tail();
+                if !expected.is_empty() {
+                    expected.remove(0);
+                }
+            }
+            assert_eq!(expected.iter().collect::<Vec<_>>(), q.iter().collect::<Vec<_>>());
+            assert_eq!(expected.first(), q.head());
+        }
+    }
+
+    #[test]
+    fn every_operation_allocates_a_bounded_number_of_cells() {
+        for &n in &[10, 1000, 10_000] {
+            assert!(most_work_per_operation::<HoodMelvilleQueue<_>>(n) <= 4);
+        }
+
+        // Tails taken part way through rotations copy no more.
+        let clones = Rc::new(Cell::new(0));
+        let mut most = 0;
+        let mut q = HoodMelvilleQueue::new();
+        for i in 0..10_000 {
+            let x = Counted(clones.clone());
+            clones.set(0);
+            q = q.snoc(x);
+            most = most.max(clones.get());
+            if i % 3 == 0 {
+                clones.set(0);
+                q = q.tail();
+                most = most.max(clones.get());
+            }
+        }
+        while !q.is_empty() {
+            clones.set(0);
+            q = q.tail();
+            most = most.max(clones.get());
+        }
+        // Two rotation steps, each copying at most two elements.
+        assert!(most <= 4);
+    }
+
+    #[test]
+    fn sync_queue_is_shared_across_threads() {
+        let q = (0..100).fold(HoodMelvilleQueue::new_sync(), |q, x| q.snoc(x));
+        let shared = q.clone();
+        let sum = thread::spawn(move || shared.into_iter().sum::<i32>()).join().unwrap();
+        assert_eq!(4950, sum);
+        assert_eq!(Some(&0), q.head());
+    }
+}
@@ -1,6 +1,53 @@
-#![allow(dead_code)]
-mod list;
-mod heap;
-mod set;
-mod tree;
-mod util;
+pub mod bankers_queue;
+pub mod binary_list;
+pub mod catenable_list;
+pub mod finger_tree;
+pub mod hood_melville_queue;
+#[macro_use]
+pub mod list;
+pub mod list_zipper;
+#[macro_use]
+pub mod heap;
+#[macro_use]
+pub mod set;
+pub mod physicists_queue;
+pub mod queue;
+pub mod real_time_queue;
+pub mod rrb_vector;
+pub mod shared;
+pub mod sharing;
+pub mod skew_list;
+pub mod stream;
+pub mod tree;
+pub mod unrolled_list;
+pub mod util;
+
+pub use bankers_queue::BankersQueue;
+pub use binary_list::BinaryList;
+pub use catenable_list::CatenableList;
+pub use finger_tree::{FingerTree, Measured, Monoid};
+pub use heap::{Heap, LeftistHeap};
+pub use hood_melville_queue::HoodMelvilleQueue;
+pub use list::{IndexOutOfRange, PersistentList, Sequence};
+pub use list_zipper::ListZipper;
+pub use physicists_queue::PhysicistsQueue;
+pub use queue::{BatchedQueue, Queue};
+pub use real_time_queue::RealTimeQueue;
+pub use rrb_vector::RrbVector;
+pub use set::{AlreadyPresent, FiniteMap, MapEntry, Set, UnbalancedMap, UnbalancedSet};
+pub use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+pub use sharing::{SharedNodes, SharingReport};
+pub use skew_list::SkewList;
+pub use stream::Stream;
+pub use tree::{BinaryTree, Tree};
+pub use unrolled_list::UnrolledList;
+pub use util::Susp;
+
+/// Brings every structure trait into scope with a single `use`.
+pub mod prelude {
+    pub use heap::Heap;
+    pub use list::Sequence;
+    pub use queue::Queue;
+    pub use set::{FiniteMap, MapEntry, Set};
+    pub use tree::BinaryTree;
+}
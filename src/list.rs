@@ -1,9 +1,52 @@
+use std::{fmt, iter, mem};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::Index;
 use std::rc::Rc;
+use std::sync::Arc;
 
-#[derive(Debug)]
-pub struct IndexOutOfRange;
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+use sharing::{allocation_bytes, SharedNodes};
+
+/// A position past the end of a sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexOutOfRange {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for IndexOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "index {} is out of range for a sequence of length {}", self.index, self.len)
+    }
+}
+
+impl Error for IndexOutOfRange {}
+
+/// Builds a `PersistentList` holding the given elements in order.
+///
+/// ```
+/// # #[macro_use] extern crate functional_data_structures;
+/// # use functional_data_structures::prelude::*;
+/// # fn main() {
+/// let l = list![1, 2, 3];
+/// assert_eq!(Some(&1), l.first());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! list {
+    ($($x:expr),* $(,)?) => {
+        $crate::PersistentList::<_>::from(vec![$($x),*])
+    };
+}
+
+pub trait Sequence<E: Clone>: Sized + Clone {
+    type Iter<'a>: Iterator<Item = &'a E> where Self: 'a, E: 'a;
+
+    fn empty() -> Self;
 
-pub trait Sequence<E: Clone>: Sized {
     fn is_empty(&self) -> bool;
 
     fn cons(&self, el: E) -> Self;
@@ -15,119 +58,617 @@ pub trait Sequence<E: Clone>: Sized {
     fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange>;
 
     fn concat(&self, other: &Self) -> Self;
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.iter().nth(index)
+    }
+
+    /// Like `nth`, but says how long the sequence is when `index` is
+    /// past its end.
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        self.nth(index).ok_or_else(|| IndexOutOfRange { index, len: self.len() })
+    }
+
+    /// Splits the sequence before `index`, which may be its length.
+    /// Only the first half is copied.
+    fn split_at(&self, index: usize) -> Result<(Self, Self), IndexOutOfRange> {
+        let (prefix, suffix) = split_prefix(self, index)?;
+        Ok((build(prefix), suffix))
+    }
+
+    /// Inserts `val` so that it ends up at `index`, which may be the
+    /// length of the sequence.
+    fn insert_at(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let (prefix, suffix) = split_prefix(self, index)?;
+        Ok(prefix.into_iter().rev().fold(suffix.cons(val), |s, el| s.cons(el)))
+    }
+
+    fn remove_at(&self, index: usize) -> Result<Self, IndexOutOfRange> {
+        let (prefix, suffix) = split_prefix(self, index)?;
+        if suffix.is_empty() {
+            return Err(IndexOutOfRange { index, len: index });
+        }
+        Ok(prefix.into_iter().rev().fold(suffix.rest(), |s, el| s.cons(el)))
+    }
+
+    fn last(&self) -> Option<&E> {
+        self.iter().last()
+    }
+
+    fn contains(&self, el: &E) -> bool
+    where E: PartialEq,
+    {
+        self.iter().any(|x| x == el)
+    }
+
+    fn fold<A, F>(&self, init: A, f: F) -> A
+    where F: FnMut(A, &E) -> A,
+    {
+        self.iter().fold(init, f)
+    }
+
+    fn reverse(&self) -> Self {
+        self.fold(Self::empty(), |reversed, x| reversed.cons(x.clone()))
+    }
+
+    /// The caller picks the kind of sequence to build, as with
+    /// `Iterator::collect`.
+    fn map<U, S, F>(&self, f: F) -> S
+    where U: Clone,
+          S: Sequence<U>,
+          F: FnMut(&E) -> U,
+    {
+        build(self.iter().map(f).collect())
+    }
+
+    fn filter<F>(&self, mut f: F) -> Self
+    where F: FnMut(&E) -> bool,
+    {
+        build(self.iter().filter(|x| f(x)).cloned().collect())
+    }
+
+    /// Copies the first `n` elements, or shares the whole sequence
+    /// when it has no more than `n`.
+    fn take(&self, n: usize) -> Self {
+        if self.drop(n).is_empty() {
+            self.clone()
+        } else {
+            build(self.iter().take(n).cloned().collect())
+        }
+    }
+
+    /// Returns the suffix after the first `n` elements; nothing is
+    /// copied.
+    fn drop(&self, n: usize) -> Self {
+        let mut suffix = self.clone();
+        for _ in 0..n {
+            if suffix.is_empty() {
+                break;
+            }
+            suffix = suffix.rest();
+        }
+        suffix
+    }
+
+    fn zip<U, O, S>(&self, other: &O) -> S
+    where U: Clone,
+          O: Sequence<U>,
+          S: Sequence<(E, U)>,
+    {
+        build(self.iter()
+              .zip(other.iter())
+              .map(|(x, y)| (x.clone(), y.clone()))
+              .collect())
+    }
 }
 
-#[derive(Debug, PartialEq)]
-enum List<E> {
+fn build<E, S>(elements: Vec<E>) -> S
+where E: Clone,
+      S: Sequence<E>,
+{
+    elements.into_iter().rev().fold(S::empty(), |s, el| s.cons(el))
+}
+
+/// Copies out the first `index` elements, leaving the rest shared.
+fn split_prefix<E, S>(sequence: &S, index: usize) -> Result<(Vec<E>, S), IndexOutOfRange>
+where E: Clone,
+      S: Sequence<E>,
+{
+    let mut prefix = Vec::new();
+    let mut suffix = sequence.clone();
+    while prefix.len() < index {
+        let el = match suffix.first() {
+            None => return Err(IndexOutOfRange { index, len: prefix.len() }),
+            Some(el) => el.clone(),
+        };
+        prefix.push(el);
+        suffix = suffix.rest();
+    }
+    Ok((prefix, suffix))
+}
+
+pub(crate) enum List<E, P: SharedPointerKind = RcK> {
     Nil,
-    Cons(E, Rc<List<E>>),
+    Cons(E, SharedPointer<List<E, P>, P>),
+}
+
+impl<E, P: SharedPointerKind> List<E, P> {
+    /// Borrows each element in turn without touching any reference
+    /// counts.
+    pub fn iter(&self) -> Iter<'_, E, P> {
+        Iter { node: self }
+    }
+}
+
+impl<E, P: SharedPointerKind> Drop for List<E, P> {
+    fn drop(&mut self) {
+        // Letting the tail drop itself recurses once per uniquely owned
+        // cell, so unlink those cells one at a time instead. Each cell is
+        // moved out of its allocation and `Nil` left in its place, which
+        // ends that allocation's drop straight away without allocating.
+        let mut next = match *self {
+            List::Cons(_, ref mut rest) => take_unique_cons::<E, P>(rest),
+            List::Nil => return,
+        };
+        while let Some(mut cell) = next {
+            next = match cell {
+                List::Cons(_, ref mut rest) => take_unique_cons::<E, P>(rest),
+                List::Nil => None,
+            };
+        }
+    }
+}
+
+/// Moves a cell out of a list nothing else points to, leaving `Nil`.
+fn take_unique_cons<E, P>(list: &mut SharedPointer<List<E, P>, P>) -> Option<List<E, P>>
+where P: SharedPointerKind,
+{
+    match P::get_mut(list) {
+        Some(cell @ &mut List::Cons(..)) => Some(mem::replace(cell, List::Nil)),
+        _ => None,
+    }
+}
+
+impl<E, P> fmt::Debug for List<E, P>
+where E: fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            List::Nil => f.write_str("Nil"),
+            List::Cons(ref head, ref rest) => {
+                f.debug_tuple("Cons").field(head).field(&**rest).finish()
+            },
+        }
+    }
+}
+
+/// Compares two lists element by element, stopping early at the first
+/// cell both lists share: everything from there on is identical. Only
+/// sound when every element compares equal to itself.
+fn compare_sharing<E, P, F>(xs: &SharedPointer<List<E, P>, P>,
+                            ys: &SharedPointer<List<E, P>, P>,
+                            mut compare: F) -> Ordering
+where P: SharedPointerKind,
+      F: FnMut(&E, &E) -> Ordering,
+{
+    let (mut xs, mut ys) = (xs, ys);
+    loop {
+        if P::ptr_eq(xs, ys) {
+            return Ordering::Equal;
+        }
+        match (&**xs, &**ys) {
+            (List::Nil, List::Nil) => return Ordering::Equal,
+            (List::Nil, List::Cons(..)) => return Ordering::Less,
+            (List::Cons(..), List::Nil) => return Ordering::Greater,
+            (List::Cons(ref x, ref x_rest), List::Cons(ref y, ref y_rest)) => {
+                match compare(x, y) {
+                    Ordering::Equal => {
+                        xs = x_rest;
+                        ys = y_rest;
+                    },
+                    different => return different,
+                }
+            },
+        }
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    node: &'a List<E, P>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        match *self.node {
+            List::Nil => None,
+            List::Cons(ref head, ref rest) => {
+                self.node = rest;
+                Some(head)
+            },
+        }
+    }
+}
+
+/// Owning iterator over a `PersistentList`.
+///
+/// Every element is cloned out, even from cells no other version
+/// shares; nothing is moved. The iterator only keeps hold of the cell
+/// it has reached, so cells that were uniquely owned are dropped,
+/// along with their elements, once it moves past them.
+pub struct IntoIter<E, P: SharedPointerKind> {
+    next: SharedPointer<List<E, P>, P>,
+}
+
+impl<E, P> Iterator for IntoIter<E, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let (head, rest) = match *self.next {
+            List::Nil => return None,
+            List::Cons(ref head, ref rest) => (head.clone(), rest.clone()),
+        };
+        self.next = rest;
+        Some(head)
+    }
+}
+
+/// A persistent singly linked list.
+///
+/// Cloning a list, or taking its `rest`, only copies a pointer; every
+/// version shares whatever cells it has in common with the others.
+pub struct PersistentList<E, P: SharedPointerKind = RcK>(SharedPointer<List<E, P>, P>);
+
+impl<E> PersistentList<E> {
+    pub fn new() -> Self {
+        PersistentList(Rc::new(List::Nil))
+    }
+}
+
+impl<E> PersistentList<E, ArcK> {
+    pub fn new_sync() -> Self {
+        PersistentList(Arc::new(List::Nil))
+    }
+}
+
+impl<E, P: SharedPointerKind> PersistentList<E, P> {
+    /// Whether both lists are the very same version, rather than just
+    /// holding equal elements.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        P::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Like `==`, but stops at the first cell both lists share, so
+    /// comparing two versions costs only the prefix where they differ.
+    /// `==` itself can't do this, as it is also there for elements that
+    /// need not equal themselves.
+    pub fn eq_sharing(&self, other: &Self) -> bool
+    where E: Eq,
+    {
+        let different = |x: &E, y: &E| if x == y { Ordering::Equal } else { Ordering::Less };
+        compare_sharing::<E, P, _>(&self.0, &other.0, different) == Ordering::Equal
+    }
+
+    /// Borrows the elements in the cells that only this list owns,
+    /// stopping at the first cell another version shares.
+    pub(crate) fn unique_iter(&self) -> impl Iterator<Item = &E> {
+        let mut cell = &self.0;
+        iter::from_fn(move || {
+            if P::strong_count(cell) != 1 {
+                return None;
+            }
+            match **cell {
+                List::Cons(ref x, ref rest) => {
+                    cell = rest;
+                    Some(x)
+                },
+                List::Nil => None,
+            }
+        })
+    }
 }
 
-impl<E> List<E> {
-    pub fn new() -> Rc<Self> {
-        Rc::new(List::Nil)
+impl<E, P: SharedPointerKind> Clone for PersistentList<E, P> {
+    fn clone(&self) -> Self {
+        PersistentList(self.0.clone())
     }
 }
 
-impl<E: Clone> Sequence<E> for Rc<List<E>> {
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for PersistentList<E, P> {
+    type Iter<'a> = Iter<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        PersistentList(P::new(List::Nil))
+    }
+
     fn is_empty(&self) -> bool {
-        match **self {
+        match *self.0 {
             List::Nil => true,
             List::Cons(_, _) => false,
         }
     }
 
     fn cons(&self, el: E) -> Self {
-        Rc::new(List::Cons(el, Rc::clone(self)))
+        PersistentList(P::new(List::Cons(el, self.0.clone())))
     }
 
     fn first(&self) -> Option<&E> {
-        match **self {
+        match *self.0 {
             List::Nil => None,
             List::Cons(ref el, _) => Some(el),
         }
     }
 
     fn rest(&self) -> Self {
-        match **self {
-            List::Nil => Rc::clone(self),
-            List::Cons(_, ref rest) => Rc::clone(rest),
+        match *self.0 {
+            List::Nil => self.clone(),
+            List::Cons(_, ref rest) => PersistentList(rest.clone()),
         }
     }
 
     fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
-        if index == 0 {
-            Ok(self.rest().cons(val))
-        } else {
-            match **self {
-                List::Nil => Err(IndexOutOfRange),
+        let mut prefix = Vec::new();
+        let mut suffix = self.clone();
+        for _ in 0..index {
+            suffix = match *suffix.0 {
+                List::Nil => return Err(IndexOutOfRange { index, len: prefix.len() }),
                 List::Cons(ref head, ref rest) => {
-                    Ok(rest.update(index - 1, val)?.cons(head.clone()))
+                    prefix.push(head.clone());
+                    PersistentList(rest.clone())
                 }
-            }
+            };
         }
+        if suffix.is_empty() {
+            return Err(IndexOutOfRange { index, len: index });
+        }
+
+        let updated = suffix.rest().cons(val);
+        Ok(prefix.into_iter().rev().fold(updated, |list, head| list.cons(head)))
     }
 
     fn concat(&self, other: &Self) -> Self {
-        match **self {
-            List::Nil => Rc::clone(other),
-            List::Cons(ref head, ref rest) => {
-                rest.concat(other).cons(head.clone())
+        let prefix = self.iter().cloned().collect::<Vec<_>>();
+        prefix.into_iter().rev().fold(other.clone(), |list, head| list.cons(head))
+    }
+
+    fn iter(&self) -> Iter<'_, E, P> {
+        self.0.iter()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for PersistentList<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for PersistentList<E, P>
+where E: fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.0.iter()).finish()
+    }
+}
+
+impl<E, P> fmt::Display for PersistentList<E, P>
+where E: fmt::Display,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, el) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
             }
+            write!(f, "{}", el)?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// Compares every element, even in a tail both lists share, since an
+/// element need not equal itself (`f64::NAN` doesn't). `PartialEq` can't
+/// tell which element types do, so this doesn't stop at a shared tail
+/// even for `Eq` ones; `eq_sharing` does.
+impl<E, P> PartialEq for PersistentList<E, P>
+where E: PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.0.iter().eq(other.0.iter())
+    }
+}
+
+impl<E: Eq, P: SharedPointerKind> Eq for PersistentList<E, P> {}
+
+/// Lexicographic, like slices: a proper prefix sorts first. For the
+/// same reason as `==`, a shared tail is compared element by element.
+impl<E, P> PartialOrd for PersistentList<E, P>
+where E: PartialOrd,
+      P: SharedPointerKind,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.0.iter().partial_cmp(other.0.iter())
+    }
+}
+
+/// Lists sharing a tail are only compared up to the shared cell, so
+/// comparing two versions costs only the prefix where they differ.
+impl<E: Ord, P: SharedPointerKind> Ord for PersistentList<E, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_sharing::<E, P, _>(&self.0, &other.0, E::cmp)
+    }
+}
+
+/// Hashes every element, shared tail included. Equal lists have to hash
+/// alike however they were built, so a cell's address can't stand in for
+/// what the rest of the list holds.
+impl<E, P> Hash for PersistentList<E, P>
+where E: Hash,
+      P: SharedPointerKind,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for el in self.0.iter() {
+            el.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+impl<E, P> Index<usize> for PersistentList<E, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    type Output = E;
+
+    fn index(&self, index: usize) -> &E {
+        match self.nth(index) {
+            Some(el) => el,
+            None => panic!("index out of bounds: the len is {} but the index is {}",
+                           self.len(), index),
         }
     }
 }
 
+impl<'a, E: Clone, P: SharedPointerKind> IntoIterator for &'a PersistentList<E, P> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E, P>;
+
+    fn into_iter(self) -> Iter<'a, E, P> {
+        self.0.iter()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> IntoIterator for PersistentList<E, P> {
+    type Item = E;
+    type IntoIter = IntoIter<E, P>;
+
+    fn into_iter(self) -> IntoIter<E, P> {
+        IntoIter { next: self.0 }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> FromIterator<E> for PersistentList<E, P> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        build(iter.into_iter().collect())
+    }
+}
 
-fn suffixes<E: Clone>(list: &Rc<List<E>>) -> Rc<List<Rc<List<E>>>> {
-    if list.is_empty() {
-        List::new().cons(Rc::clone(list))
-    } else {
-        suffixes(&list.rest()).cons(Rc::clone(list))
+/// Appends the new elements after the existing ones. The existing
+/// cells are copied, since nothing else can change in place.
+impl<E: Clone, P: SharedPointerKind> Extend<E> for PersistentList<E, P> {
+    fn extend<I: IntoIterator<Item = E>>(&mut self, iter: I) {
+        let elements = self.iter().cloned().chain(iter).collect::<Vec<_>>();
+        *self = build(elements);
     }
 }
 
+impl<E: Clone, P: SharedPointerKind> From<Vec<E>> for PersistentList<E, P> {
+    fn from(elements: Vec<E>) -> Self {
+        build(elements)
+    }
+}
+
+impl<'a, E: Clone, P: SharedPointerKind> From<&'a [E]> for PersistentList<E, P> {
+    fn from(elements: &'a [E]) -> Self {
+        elements.iter().rev().fold(Self::empty(), |list, el| list.cons(el.clone()))
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> From<PersistentList<E, P>> for Vec<E> {
+    fn from(list: PersistentList<E, P>) -> Self {
+        list.iter().cloned().collect()
+    }
+}
+
+impl<E, P: SharedPointerKind> SharedNodes for PersistentList<E, P> {
+    fn node_bytes() -> usize {
+        allocation_bytes::<List<E, P>>()
+    }
+
+    fn visit_nodes<F>(&self, mut visit: F)
+    where F: FnMut(usize) -> bool,
+    {
+        let mut node = &self.0;
+        while visit(&**node as *const List<E, P> as usize) {
+            match **node {
+                List::Nil => break,
+                List::Cons(_, ref rest) => node = rest,
+            }
+        }
+    }
+}
+
+pub fn suffixes<E, P>(list: &PersistentList<E, P>) -> PersistentList<PersistentList<E, P>, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    let mut all = vec![list.clone()];
+    while !all[all.len() - 1].is_empty() {
+        let next = all[all.len() - 1].rest();
+        all.push(next);
+    }
+
+    build(all)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use sharing::SharingReport;
+
     #[test]
     fn first_on_nil() {
-        let l: Rc<List<u8>> = List::new();
+        let l: PersistentList<u8> = PersistentList::new();
         assert_eq!(l.first(), None);
     }
 
     #[test]
     fn first_on_singleton() {
-        let l = List::new().cons(1);
+        let l = PersistentList::new().cons(1);
         let el = l.first();
         assert_eq!(el, Some(&1));
     }
 
     #[test]
     fn does_not_move_on_cons() {
-        let l = List::new();
+        let l = PersistentList::new();
         let l2 = l.cons(1);
-        assert_eq!(*l, List::Nil);
+        assert!(l.is_empty());
         assert_eq!(l2.first(), Some(&1));
     }
 
     #[test]
     fn rest_on_nil_is_nil() {
-        let l: Rc<List<u8>> = List::new();
-        assert_eq!(*(l.rest()), List::Nil);
+        let l: PersistentList<u8> = PersistentList::new();
+        assert!(l.rest().is_empty());
     }
 
     #[test]
     fn rest_on_list_drops_first_item() {
-        let l = List::new().cons(1).cons(2);
+        let l = PersistentList::new().cons(1).cons(2);
         let rest = l.rest();
         assert_eq!(rest.first(), Some(&1));
     }
 
     #[test]
     fn update_copies_changed_node() {
-        let l = List::new().cons(1).cons(2);
+        let l = PersistentList::new().cons(1).cons(2);
         assert_eq!(l.first(), Some(&2));
         assert_eq!(l.rest().first(), Some(&1));
 
@@ -135,25 +676,32 @@ mod tests {
         assert_eq!(new_l.first(), Some(&4));
         assert_eq!(new_l.rest().first(), Some(&1));
 
-        assert!(Rc::ptr_eq(&l.rest(), &new_l.rest()));
+        assert!(PersistentList::ptr_eq(&l.rest(), &new_l.rest()));
     }
 
     #[test]
     fn update_copies_all_dependent_nodes() {
-        let l = List::new().cons(1).cons(2).cons(3);
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
 
         let new_l = l.update(1, 4).unwrap();
         assert_eq!(new_l.first(), Some(&3));
         assert_eq!(new_l.rest().first(), Some(&4));
         assert_eq!(new_l.rest().rest().first(), Some(&1));
 
-        assert!(Rc::ptr_eq(&l.rest().rest(), &new_l.rest().rest()));
+        assert!(PersistentList::ptr_eq(&l.rest().rest(), &new_l.rest().rest()));
+    }
+
+    #[test]
+    fn update_past_the_end() {
+        let l = list![1, 2, 3];
+        assert_eq!(Err(IndexOutOfRange { index: 3, len: 3 }), l.update(3, 9));
+        assert_eq!(Err(IndexOutOfRange { index: usize::MAX, len: 3 }), l.update(usize::MAX, 9));
     }
 
     #[test]
     fn concat_joins_two_lists() {
-        let xs = List::new().cons(1).cons(2);
-        let ys = List::new().cons(3).cons(4);
+        let xs = PersistentList::new().cons(1).cons(2);
+        let ys = PersistentList::new().cons(3).cons(4);
 
         let zs = ys.concat(&xs);
 
@@ -165,8 +713,8 @@ mod tests {
 
     #[test]
     fn concat_leaves_both_lists_usable() {
-        let xs = List::new().cons(1).cons(2);
-        let ys = List::new().cons(3).cons(4);
+        let xs = PersistentList::new().cons(1).cons(2);
+        let ys = PersistentList::new().cons(3).cons(4);
 
         let _zs = ys.concat(&xs);
         assert_eq!(xs.first(), Some(&2));
@@ -179,7 +727,7 @@ mod tests {
 
     #[test]
     fn suffixes_of_nil() {
-        let l: Rc<List<u8>> = List::new();
+        let l: PersistentList<u8> = PersistentList::new();
         let s = suffixes(&l);
 
         assert_eq!(s.first(), Some(&l));
@@ -187,21 +735,460 @@ mod tests {
 
     #[test]
     fn suffixes_of_one() {
-        let l = List::new().cons(1);
+        let l = PersistentList::new().cons(1);
         let s = suffixes(&l);
 
         assert_eq!(s.first().unwrap().first(), Some(&1));
-        assert_eq!(**(s.rest().first().unwrap()), List::Nil);
+        assert!(s.rest().first().unwrap().is_empty());
     }
 
     #[test]
     fn suffixes_of_many() {
-        let l = List::new().cons(1).cons(2);
+        let l = PersistentList::new().cons(1).cons(2);
         let s = suffixes(&l);
 
         assert_eq!(s.first().unwrap().first(), Some(&2));
         assert_eq!(s.first().unwrap().rest().first(), Some(&1));
         assert_eq!(s.rest().first().unwrap().first(), Some(&1));
-        assert_eq!(**(s.rest().rest().first().unwrap()), List::Nil);
+        assert!(s.rest().rest().first().unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_deep_in_long_list() {
+        let mut l = PersistentList::new();
+        for i in 0..1_000_000 {
+            l = l.cons(i);
+        }
+
+        let new_l = l.update(999_998, 7).unwrap();
+        let (mut old, mut new) = (l.clone(), new_l.clone());
+        for _ in 0..999_998 {
+            assert_eq!(old.first(), new.first());
+            old = old.rest();
+            new = new.rest();
+        }
+        assert_eq!(new.first(), Some(&7));
+        assert!(PersistentList::ptr_eq(&old.rest(), &new.rest()));
+        assert_eq!(Err(IndexOutOfRange { index: 1_000_001, len: 1_000_000 }), l.update(1_000_001, 7));
+    }
+
+    #[test]
+    fn get_reports_index_and_len() {
+        let l = list![1, 2, 3];
+        assert_eq!(Ok(&3), l.get(2));
+
+        let err = l.get(5).unwrap_err();
+        assert_eq!(IndexOutOfRange { index: 5, len: 3 }, err);
+        assert_eq!("index 5 is out of range for a sequence of length 3", err.to_string());
+    }
+
+    #[test]
+    fn split_at_shares_the_suffix() {
+        let l = list![1, 2, 3, 4];
+        let (front, back) = l.split_at(1).unwrap();
+        assert_eq!(list![1], front);
+        assert!(PersistentList::ptr_eq(&l.rest(), &back));
+
+        let (front, back) = l.split_at(4).unwrap();
+        assert_eq!(l, front);
+        assert!(back.is_empty());
+        assert_eq!(Err(IndexOutOfRange { index: 5, len: 4 }), l.split_at(5));
+        assert_eq!(Err(IndexOutOfRange { index: usize::MAX, len: 4 }), l.split_at(usize::MAX));
+    }
+
+    #[test]
+    fn insert_at_any_position() {
+        let l = list![1, 2, 3];
+        assert_eq!(list![0, 1, 2, 3], l.insert_at(0, 0).unwrap());
+        assert_eq!(list![1, 2, 9, 3], l.insert_at(2, 9).unwrap());
+        assert_eq!(list![1, 2, 3, 4], l.insert_at(3, 4).unwrap());
+        assert!(PersistentList::ptr_eq(&l.rest().rest(), &l.insert_at(2, 9).unwrap().drop(3)));
+        assert_eq!(Err(IndexOutOfRange { index: 4, len: 3 }), l.insert_at(4, 4));
+        assert_eq!(Err(IndexOutOfRange { index: usize::MAX, len: 3 }), l.insert_at(usize::MAX, 4));
+    }
+
+    #[test]
+    fn remove_at_any_position() {
+        let l = list![1, 2, 3];
+        assert_eq!(list![2, 3], l.remove_at(0).unwrap());
+        assert_eq!(list![1, 3], l.remove_at(1).unwrap());
+        assert_eq!(list![1, 2], l.remove_at(2).unwrap());
+        assert_eq!(Err(IndexOutOfRange { index: 3, len: 3 }), l.remove_at(3));
+        assert_eq!(Err(IndexOutOfRange { index: usize::MAX, len: 3 }), l.remove_at(usize::MAX));
+    }
+
+    #[test]
+    fn concat_long_lists() {
+        let mut xs = PersistentList::new();
+        let mut ys = PersistentList::new();
+        for i in 0..1_000_000 {
+            xs = xs.cons(i);
+            ys = ys.cons(i);
+        }
+
+        let zs = ys.concat(&xs);
+        assert_eq!(zs.first(), Some(&999_999));
+        assert_eq!(ys.first(), Some(&999_999));
+    }
+
+    #[test]
+    fn suffixes_of_long_list() {
+        let mut l = PersistentList::new();
+        for i in 0..1_000_000 {
+            l = l.cons(i);
+        }
+
+        let s = suffixes(&l);
+        assert!(PersistentList::ptr_eq(s.first().unwrap(), &l));
+        assert!(PersistentList::ptr_eq(s.rest().first().unwrap(), &l.rest()));
+    }
+
+    #[test]
+    fn iter_borrows_in_order() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        let items = l.iter().collect::<Vec<_>>();
+        assert_eq!(vec![&3, &2, &1], items);
+    }
+
+    #[test]
+    fn for_loop_over_borrowed_list() {
+        let l = PersistentList::new().cons(1).cons(2);
+        let mut total = 0;
+        for x in &l {
+            total += *x;
+        }
+        assert_eq!(3, total);
+    }
+
+    #[test]
+    fn into_iter_leaves_shared_tail_usable() {
+        let l = PersistentList::new().cons(1).cons(2);
+        let items = l.clone().into_iter().collect::<Vec<_>>();
+        assert_eq!(vec![2, 1], items);
+        assert_eq!(l.first(), Some(&2));
+        assert_eq!(l.rest().first(), Some(&1));
+    }
+
+    #[test]
+    fn collect_into_list() {
+        let l: PersistentList<u8> = (1..4).collect();
+        assert_eq!(l.first(), Some(&1));
+        assert_eq!(l.rest().first(), Some(&2));
+        assert_eq!(l.rest().rest().first(), Some(&3));
+        assert!(l.rest().rest().rest().is_empty());
+    }
+
+    #[test]
+    fn collect_empty_into_list() {
+        let l: PersistentList<u8> = None.into_iter().collect();
+        assert_eq!(l, PersistentList::new());
+    }
+
+    #[test]
+    fn extend_appends_elements() {
+        let mut l: PersistentList<u8> = vec![1, 2].into_iter().collect();
+        l.extend(vec![3, 4]);
+        assert_eq!(vec![&1, &2, &3, &4], l.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn index_into_list() {
+        let l = PersistentList::new().cons(1).cons(2);
+        assert_eq!(2, l[0]);
+        assert_eq!(1, l[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn index_past_end_panics() {
+        let l = PersistentList::new().cons(1);
+        let _ = l[1];
+    }
+
+    #[test]
+    fn list_macro_keeps_order() {
+        let l = list![1, 2, 3];
+        assert_eq!(l, PersistentList::new().cons(3).cons(2).cons(1));
+        let empty: PersistentList<u8> = list![];
+        assert!(empty.is_empty());
+        assert_eq!(list![1, 2,], list![1, 2]);
+    }
+
+    #[test]
+    fn debug_list() {
+        assert_eq!("[1, 2, 3]", format!("{:?}", list![1, 2, 3]));
+        assert_eq!("[\"a\"]", format!("{:?}", list!["a"]));
+    }
+
+    #[test]
+    fn display_list() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        assert_eq!("[3, 2, 1]", l.to_string());
+        assert_eq!("[]", PersistentList::<u8>::new().to_string());
+    }
+
+    #[test]
+    fn default_is_empty() {
+        let l: PersistentList<u8> = Default::default();
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn convert_from_and_into_vec() {
+        let l = PersistentList::<u8>::from(vec![1, 2, 3]);
+        assert_eq!(l, PersistentList::from(&[1, 2, 3][..]));
+        assert_eq!(vec![1, 2, 3], Vec::from(l));
+    }
+
+    #[test]
+    fn equal_lists_hash_equal() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(l: &PersistentList<u8>) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            l.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let xs = PersistentList::new().cons(1).cons(2);
+        let ys = PersistentList::from(vec![2, 1]);
+        assert_eq!(hash_of(&xs), hash_of(&ys));
+        assert!(hash_of(&xs) != hash_of(&xs.rest()));
+    }
+
+    #[test]
+    fn lists_compare_lexicographically() {
+        assert!(list![1, 2] < list![1, 3]);
+        assert!(list![1, 2] < list![1, 2, 0]);
+        assert!(list![2] > list![1, 9, 9]);
+        assert_eq!(Ordering::Equal, list![1, 2].cmp(&list![1, 2]));
+        assert_eq!(None, list![1.0, f64::NAN].partial_cmp(&list![1.0, 2.0]));
+    }
+
+    #[test]
+    fn comparing_versions_stops_at_shared_tail() {
+        use std::cell::Cell;
+
+        thread_local!(static COMPARISONS: Cell<usize> = const { Cell::new(0) });
+
+        #[derive(Clone, Debug)]
+        struct Counted(u32);
+
+        impl PartialEq for Counted {
+            fn eq(&self, other: &Self) -> bool {
+                COMPARISONS.with(|c| c.set(c.get() + 1));
+                self.0 == other.0
+            }
+        }
+
+        impl Eq for Counted {}
+
+        impl PartialOrd for Counted {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Counted {
+            fn cmp(&self, other: &Self) -> Ordering {
+                COMPARISONS.with(|c| c.set(c.get() + 1));
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let mut tail = PersistentList::new();
+        for i in 0..10_000 {
+            tail = tail.cons(Counted(i));
+        }
+        let xs = tail.cons(Counted(1)).cons(Counted(2));
+        let ys = tail.cons(Counted(1)).cons(Counted(2));
+        let zs = tail.cons(Counted(3));
+
+        assert_eq!(Ordering::Equal, xs.cmp(&ys));
+        assert_eq!(Ordering::Less, xs.cmp(&zs));
+        assert_eq!(Ordering::Equal, xs.cmp(&xs.clone()));
+        assert_eq!(3, COMPARISONS.with(|c| c.get()));
+
+        assert!(xs.eq_sharing(&ys));
+        assert!(!xs.eq_sharing(&zs));
+        assert!(!xs.eq_sharing(&tail));
+        assert!(xs.eq_sharing(&xs.clone()));
+        assert_eq!(7, COMPARISONS.with(|c| c.get()));
+    }
+
+    #[test]
+    fn nan_is_not_equal_to_itself_through_a_shared_list() {
+        let l = list![1.0, f64::NAN];
+        assert!(l != l.clone());
+        assert_eq!(None, l.partial_cmp(&l.clone()));
+        assert!(l.rest() != l.rest());
+    }
+
+    #[test]
+    fn lists_as_map_keys() {
+        use std::collections::HashMap;
+        use set::{FiniteMap, UnbalancedMap};
+
+        let m = UnbalancedMap::<(PersistentList<u8>, &str)>::empty()
+            .bind(list![1, 2], "a")
+            .bind(list![1], "b");
+        assert_eq!(Some(&"a"), m.lookup(&list![1, 2]));
+        assert_eq!(Some(&"b"), m.lookup(&list![1]));
+
+        let mut h = HashMap::new();
+        h.insert(list![1, 2], "a");
+        assert_eq!(Some(&"a"), h.get(&list![1, 2]));
+    }
+
+    #[test]
+    fn sharing_between_list_versions() {
+        let xs = list![1, 2, 3];
+        let ys = xs.rest().cons(4).cons(5);
+        let zs = list![1, 2, 3];
+
+        let report = SharingReport::of(&[xs.clone(), ys, zs]);
+        assert_eq!(3, report.shared_nodes);
+        assert_eq!(vec![1, 2, 4], report.unique_nodes);
+        assert_eq!(vec![4, 5, 4], report.reachable_nodes);
+        assert_eq!(10, report.total_nodes());
+        assert_eq!(10 * report.node_bytes, report.estimated_bytes());
+        assert_eq!(13 * report.node_bytes, report.unshared_bytes());
+
+        let report = SharingReport::of(&[xs.clone(), xs]);
+        assert_eq!(4, report.shared_nodes);
+        assert_eq!(vec![0, 0], report.unique_nodes);
+    }
+
+    #[test]
+    fn len_of_list() {
+        let l: PersistentList<u8> = PersistentList::new();
+        assert_eq!(0, l.len());
+        assert_eq!(3, l.cons(1).cons(2).cons(3).len());
+    }
+
+    #[test]
+    fn nth_and_last() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        assert_eq!(Some(&3), l.nth(0));
+        assert_eq!(Some(&1), l.nth(2));
+        assert_eq!(None, l.nth(3));
+        assert_eq!(Some(&1), l.last());
+        assert_eq!(None, PersistentList::<u8>::new().last());
+    }
+
+    #[test]
+    fn contains_element() {
+        let l = PersistentList::new().cons(1).cons(2);
+        assert!(l.contains(&1));
+        assert!(!l.contains(&3));
+    }
+
+    #[test]
+    fn fold_visits_in_order() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        assert_eq!(321, l.fold(0, |acc, x| acc * 10 + x));
+    }
+
+    #[test]
+    fn reverse_list() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        let r = l.reverse();
+        assert_eq!(vec![&1, &2, &3], r.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn map_into_other_element_type() {
+        let l = PersistentList::new().cons(1).cons(2);
+        let m: PersistentList<String> = l.map(|x| x.to_string());
+        assert_eq!(Some(&"2".to_string()), m.first());
+        assert_eq!(Some(&"1".to_string()), m.rest().first());
+    }
+
+    #[test]
+    fn filter_keeps_order() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3).cons(4);
+        let evens = l.filter(|x| x % 2 == 0);
+        assert_eq!(vec![&4, &2], evens.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn take_copies_prefix() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        let t = l.take(2);
+        assert_eq!(vec![&3, &2], t.iter().collect::<Vec<_>>());
+        assert!(!PersistentList::ptr_eq(&t, &l));
+    }
+
+    #[test]
+    fn take_everything_shares_list() {
+        let l = PersistentList::new().cons(1).cons(2);
+        assert!(PersistentList::ptr_eq(&l.take(2), &l));
+        assert!(PersistentList::ptr_eq(&l.take(5), &l));
+    }
+
+    #[test]
+    fn drop_shares_suffix() {
+        let l = PersistentList::new().cons(1).cons(2).cons(3);
+        assert!(PersistentList::ptr_eq(&l.drop(0), &l));
+        assert!(PersistentList::ptr_eq(&l.drop(2), &l.rest().rest()));
+        assert!(l.drop(5).is_empty());
+    }
+
+    #[test]
+    fn zip_stops_at_shorter() {
+        let xs = PersistentList::new().cons(1).cons(2).cons(3);
+        let ys = PersistentList::new().cons("a").cons("b");
+        let zs: PersistentList<(u8, &str)> = xs.zip(&ys);
+        assert_eq!(vec![&(3, "b"), &(2, "a")], zs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dropping_long_list_does_not_overflow() {
+        let mut l = PersistentList::new();
+        for i in 0..1_000_000 {
+            l = l.cons(i);
+        }
+        drop(l);
+    }
+
+    #[test]
+    fn dropping_version_keeps_shared_tail() {
+        let mut tail = PersistentList::new();
+        for i in 0..100_000 {
+            tail = tail.cons(i);
+        }
+        let mut l = tail.clone();
+        for i in 0..100_000 {
+            l = l.cons(i);
+        }
+
+        drop(l);
+        assert_eq!(1, Rc::strong_count(&tail.0));
+        assert_eq!(tail.first(), Some(&99_999));
+        assert_eq!(tail.rest().first(), Some(&99_998));
+    }
+
+    #[test]
+    fn dropping_a_list_allocates_nothing() {
+        use sharing::allocations;
+
+        let tail = (0..1000).collect::<PersistentList<u32>>();
+        let l = (0..1000).fold(tail.clone(), |l, i| l.cons(i));
+        assert_eq!(0, allocations::counting(|| drop(l)));
+        assert_eq!(0, allocations::counting(|| drop(tail)));
+    }
+
+    #[test]
+    fn sync_list_is_shared_across_threads() {
+        use std::thread;
+
+        let l = PersistentList::new_sync().cons(1).cons(2);
+        let l2 = l.clone();
+        let handle = thread::spawn(move || l2.update(0, 3).unwrap());
+
+        let new_l = handle.join().unwrap();
+        assert_eq!(new_l.first(), Some(&3));
+        assert_eq!(l.first(), Some(&2));
+        assert!(PersistentList::ptr_eq(&l.rest(), &new_l.rest()));
     }
 }
@@ -0,0 +1,240 @@
+use std::fmt;
+
+use list::{PersistentList, Sequence};
+use shared::{RcK, SharedPointerKind};
+
+/// A cursor into a `PersistentList`, as in Huet's zipper.
+///
+/// The elements in front of the cursor are kept in a list of their own,
+/// nearest first, and the focus is the first element of the rest. Moving
+/// the cursor one step and editing at it are O(1), since only the cells
+/// next to the cursor change; turning the zipper back into a list costs
+/// one `cons` per element in front of the cursor.
+pub struct ListZipper<E, P: SharedPointerKind = RcK> {
+    /// How many elements are in front of the cursor.
+    index: usize,
+    before: PersistentList<E, P>,
+    after: PersistentList<E, P>,
+}
+
+impl<E: Clone, P: SharedPointerKind> ListZipper<E, P> {
+    /// The number of elements in front of the cursor.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    pub fn is_at_start(&self) -> bool {
+        self.before.is_empty()
+    }
+
+    /// Whether the cursor is past the last element, with nothing in focus.
+    pub fn is_at_end(&self) -> bool {
+        self.after.is_empty()
+    }
+
+    pub fn focus(&self) -> Option<&E> {
+        self.after.first()
+    }
+
+    /// Moves the focus to the previous element, or returns `None` at
+    /// the start of the list.
+    pub fn move_left(&self) -> Option<Self> {
+        let el = self.before.first()?;
+        Some(ListZipper {
+            index: self.index - 1,
+            before: self.before.rest(),
+            after: self.after.cons(el.clone()),
+        })
+    }
+
+    /// Moves the focus to the next element, or past the last one.
+    /// Returns `None` when there is no focus to move past.
+    pub fn move_right(&self) -> Option<Self> {
+        let el = self.after.first()?;
+        Some(ListZipper {
+            index: self.index + 1,
+            before: self.before.cons(el.clone()),
+            after: self.after.rest(),
+        })
+    }
+
+    /// Puts `val` at the cursor, where it becomes the focus; the old
+    /// focus follows it.
+    pub fn insert(&self, val: E) -> Self {
+        ListZipper {
+            index: self.index,
+            before: self.before.clone(),
+            after: self.after.cons(val),
+        }
+    }
+
+    /// Removes the focus, which passes to the element after it. Returns
+    /// `None` when there is no focus.
+    pub fn delete(&self) -> Option<Self> {
+        if self.after.is_empty() {
+            return None;
+        }
+        Some(ListZipper {
+            index: self.index,
+            before: self.before.clone(),
+            after: self.after.rest(),
+        })
+    }
+
+    /// Swaps the focus for `val`, or returns `None` when there is no
+    /// focus.
+    pub fn replace(&self, val: E) -> Option<Self> {
+        if self.after.is_empty() {
+            return None;
+        }
+        Some(ListZipper {
+            index: self.index,
+            before: self.before.clone(),
+            after: self.after.rest().cons(val),
+        })
+    }
+
+    /// Rebuilds the whole list. The elements from the focus on are
+    /// shared with the zipper, not copied.
+    pub fn to_list(&self) -> PersistentList<E, P> {
+        self.before.iter().fold(self.after.clone(), |list, el| list.cons(el.clone()))
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for ListZipper<E, P> {
+    fn clone(&self) -> Self {
+        ListZipper {
+            index: self.index,
+            before: self.before.clone(),
+            after: self.after.clone(),
+        }
+    }
+}
+
+/// Starts with the first element of the list in focus.
+impl<E: Clone, P: SharedPointerKind> From<PersistentList<E, P>> for ListZipper<E, P> {
+    fn from(list: PersistentList<E, P>) -> Self {
+        ListZipper { index: 0, before: PersistentList::empty(), after: list }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> From<ListZipper<E, P>> for PersistentList<E, P> {
+    fn from(zipper: ListZipper<E, P>) -> Self {
+        zipper.to_list()
+    }
+}
+
+impl<E, P> fmt::Debug for ListZipper<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ListZipper")
+            .field("before", &self.before.reverse())
+            .field("after", &self.after)
+            .finish()
+    }
+}
+
+impl<E, P> PartialEq for ListZipper<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.before == other.before && self.after == other.after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zipper_of(s: &str) -> ListZipper<char> {
+        ListZipper::from(s.chars().collect::<PersistentList<_>>())
+    }
+
+    fn text(zipper: &ListZipper<char>) -> String {
+        zipper.to_list().iter().collect()
+    }
+
+    #[test]
+    fn empty_zipper_has_no_focus() {
+        let z = zipper_of("");
+        assert!(z.is_at_start());
+        assert!(z.is_at_end());
+        assert_eq!(None, z.focus());
+        assert!(z.move_left().is_none());
+        assert!(z.move_right().is_none());
+        assert!(z.delete().is_none());
+        assert!(z.replace('x').is_none());
+    }
+
+    #[test]
+    fn moves_across_every_element() {
+        let mut z = zipper_of("abc");
+        for (i, c) in "abc".chars().enumerate() {
+            assert_eq!(i, z.position());
+            assert_eq!(Some(&c), z.focus());
+            z = z.move_right().unwrap();
+        }
+        assert!(z.is_at_end());
+        assert!(z.move_right().is_none());
+
+        let z = z.move_left().unwrap();
+        assert_eq!(Some(&'c'), z.focus());
+        assert_eq!(2, z.position());
+    }
+
+    #[test]
+    fn edits_at_the_cursor() {
+        let z = zipper_of("held").move_right().unwrap().move_right().unwrap().move_right().unwrap();
+        assert_eq!(Some(&'d'), z.focus());
+
+        let z = z.insert('l');
+        assert_eq!("helld", text(&z));
+        let z = z.replace('o').unwrap();
+        assert_eq!("helod", text(&z));
+        let z = z.move_right().unwrap().delete().unwrap();
+        assert_eq!("helo", text(&z));
+        assert!(z.is_at_end());
+        assert_eq!("helo!", text(&z.insert('!')));
+    }
+
+    #[test]
+    fn edits_leave_older_versions_alone() {
+        let z = zipper_of("abc").move_right().unwrap();
+        let deleted = z.delete().unwrap();
+        let replaced = z.replace('x').unwrap();
+        assert_eq!("abc", text(&z));
+        assert_eq!("ac", text(&deleted));
+        assert_eq!("axc", text(&replaced));
+    }
+
+    #[test]
+    fn to_list_shares_the_suffix() {
+        let list = "abcd".chars().collect::<PersistentList<_>>();
+        let z = ListZipper::from(list.clone()).move_right().unwrap().move_right().unwrap();
+        assert!(list.drop(2).ptr_eq(&z.to_list().drop(2)));
+        assert_eq!(list, PersistentList::from(z));
+    }
+
+    #[test]
+    fn typing_a_long_line_is_linear() {
+        // Every keystroke inserts behind the cursor; with `update` on a
+        // list this was quadratic in the length of the line.
+        let mut z = ListZipper::from(PersistentList::new());
+        for i in 0..100_000 {
+            z = z.insert(i).move_right().unwrap();
+        }
+        assert_eq!(100_000, z.position());
+        let line = z.to_list();
+        assert_eq!(100_000, line.len());
+        assert_eq!(Some(&99_999), line.last());
+    }
+
+    #[test]
+    fn debug_shows_both_sides_in_order() {
+        let z = zipper_of("abc").move_right().unwrap().move_right().unwrap();
+        assert_eq!("ListZipper { before: ['a', 'b'], after: ['c'] }", format!("{:?}", z));
+    }
+}
@@ -0,0 +1,326 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use list::{self, PersistentList, Sequence};
+use queue::Queue;
+use shared::RcK;
+use util::Susp;
+
+/// Okasaki's physicist's queue, from section 6.4.2.
+///
+/// The front is a single suspended list rather than a stream, so a
+/// rotation builds `front ++ reverse rear` in one go the first time it
+/// is forced. To let `head` work without forcing it, the queue also
+/// keeps an evaluated copy of a prefix of the front, the working copy,
+/// and only forces the front again once that runs out. The rear stays
+/// no longer than the front, and `snoc`, `head` and `tail` are O(1)
+/// amortized under persistent use. `Susp` ties the front to `Rc`, so
+/// there is no `new_sync` for this one.
+pub struct PhysicistsQueue<E: 'static> {
+    working: PersistentList<E>,
+    front_len: usize,
+    front: Susp<PersistentList<E>>,
+    /// The suspended `rest`s of the front taken since it was last known
+    /// to be forced, newest first. Forcing goes through them oldest
+    /// first, so no suspension has to force the one before it and a
+    /// long run of `tail`s can't overflow the stack.
+    pending: PersistentList<Susp<PersistentList<E>>>,
+    rear_len: usize,
+    /// The back of the queue, last element first.
+    rear: PersistentList<E>,
+}
+
+impl<E: Clone + 'static> PhysicistsQueue<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+
+    /// Rotates the rear onto the front once it gets longer, then makes
+    /// sure the working copy is only empty when the queue is.
+    fn checked(working: PersistentList<E>,
+               front_len: usize,
+               front: Susp<PersistentList<E>>,
+               pending: PersistentList<Susp<PersistentList<E>>>,
+               rear_len: usize,
+               rear: PersistentList<E>) -> Self {
+        let queue = PhysicistsQueue { working, front_len, front, pending, rear_len, rear };
+        if queue.rear_len > queue.front_len {
+            let forced = queue.force_front().clone();
+            let rear = queue.rear.clone();
+            let rotated = forced.clone();
+            Self::checked(forced,
+                          queue.front_len + queue.rear_len,
+                          Susp::delay(move || rotated.concat(&rear.reverse())),
+                          PersistentList::empty(),
+                          0,
+                          PersistentList::empty())
+        } else if queue.working.is_empty() && !queue.is_empty() {
+            Self::checked(queue.force_front().clone(),
+                          queue.front_len,
+                          queue.front.clone(),
+                          PersistentList::empty(),
+                          queue.rear_len,
+                          queue.rear.clone())
+        } else {
+            queue
+        }
+    }
+
+    fn force_front(&self) -> &PersistentList<E> {
+        let unforced = self.pending.iter()
+            .take_while(|susp| !susp.is_forced())
+            .collect::<Vec<_>>();
+        for susp in unforced.into_iter().rev() {
+            susp.force();
+        }
+        self.front.force()
+    }
+
+    /// Borrows the elements from the front of the queue to the back,
+    /// forcing the front first.
+    pub fn iter(&self) -> Iter<'_, E> {
+        Iter { front: self.force_front().iter(), rear: self.rear.iter().collect() }
+    }
+}
+
+impl<E> Clone for PhysicistsQueue<E> {
+    fn clone(&self) -> Self {
+        PhysicistsQueue {
+            working: self.working.clone(),
+            front_len: self.front_len,
+            front: self.front.clone(),
+            pending: self.pending.clone(),
+            rear_len: self.rear_len,
+            rear: self.rear.clone(),
+        }
+    }
+}
+
+impl<E> Drop for PhysicistsQueue<E> {
+    fn drop(&mut self) {
+        // Each pending `rest` holds on to the one before it, so letting
+        // the newest go would free the whole run recursively. Hold the
+        // ones only this queue owns and release them newest first.
+        let pending = self.pending.unique_iter().cloned().collect::<Vec<_>>();
+        self.front = Susp::forced(PersistentList::new());
+        self.pending = PersistentList::new();
+        drop(pending);
+    }
+}
+
+impl<E: Clone + 'static> Queue for PhysicistsQueue<E> {
+    type Item = E;
+
+    fn empty() -> Self {
+        PhysicistsQueue {
+            working: PersistentList::empty(),
+            front_len: 0,
+            front: Susp::forced(PersistentList::empty()),
+            pending: PersistentList::empty(),
+            rear_len: 0,
+            rear: PersistentList::empty(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    fn snoc(&self, x: E) -> Self {
+        Self::checked(self.working.clone(),
+                      self.front_len,
+                      self.front.clone(),
+                      self.pending.clone(),
+                      self.rear_len + 1,
+                      self.rear.cons(x))
+    }
+
+    fn head(&self) -> Option<&E> {
+        self.working.first()
+    }
+
+    fn tail(&self) -> Self {
+        if self.is_empty() {
+            return self.clone();
+        }
+        let (front, pending) = match self.front.get() {
+            Some(front) => (Susp::forced(front.rest()), PersistentList::empty()),
+            None => {
+                let forced = self.front.clone();
+                let front = Susp::delay(move || forced.force().rest());
+                (front.clone(), self.pending.cons(front))
+            },
+        };
+        Self::checked(self.working.rest(),
+                      self.front_len - 1,
+                      front,
+                      pending,
+                      self.rear_len,
+                      self.rear.clone())
+    }
+}
+
+pub struct Iter<'a, E: 'a> {
+    front: list::Iter<'a, E, RcK>,
+    /// The back of the queue, last element first.
+    rear: Vec<&'a E>,
+}
+
+impl<'a, E> Iterator for Iter<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.front.next().or_else(|| self.rear.pop())
+    }
+}
+
+/// Owning iterator over a `PhysicistsQueue`, which hands out the
+/// elements by taking the `tail` of the queue one step at a time.
+pub struct IntoIter<E: 'static> {
+    queue: PhysicistsQueue<E>,
+}
+
+impl<E: Clone + 'static> Iterator for IntoIter<E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let x = self.queue.head()?.clone();
+        self.queue = self.queue.tail();
+        Some(x)
+    }
+}
+
+impl<E: Clone + 'static> Default for PhysicistsQueue<E> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone + fmt::Debug + 'static> fmt::Debug for PhysicistsQueue<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E: Clone + PartialEq + 'static> PartialEq for PhysicistsQueue<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.front_len + self.rear_len == other.front_len + other.rear_len
+            && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, E: Clone + 'static> IntoIterator for &'a PhysicistsQueue<E> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E>;
+
+    fn into_iter(self) -> Iter<'a, E> {
+        self.iter()
+    }
+}
+
+impl<E: Clone + 'static> IntoIterator for PhysicistsQueue<E> {
+    type Item = E;
+    type IntoIter = IntoIter<E>;
+
+    fn into_iter(self) -> IntoIter<E> {
+        IntoIter { queue: self }
+    }
+}
+
+/// The first element of the iterator ends up at the head of the queue.
+impl<E: Clone + 'static> FromIterator<E> for PhysicistsQueue<E> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        let front = iter.into_iter().collect::<PersistentList<_>>();
+        PhysicistsQueue {
+            working: front.clone(),
+            front_len: front.len(),
+            front: Susp::forced(front),
+            pending: PersistentList::empty(),
+            rear_len: 0,
+            rear: PersistentList::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use queue::checks::{self, drain, queue_of, Counted};
+
+    #[test]
+    fn behaves_as_a_fifo() {
+        checks::check_fifo::<PhysicistsQueue<usize>>();
+    }
+
+    #[test]
+    fn working_copy_only_empty_with_the_queue() {
+        let mut q = PhysicistsQueue::new();
+        for i in 0..100 {
+            q = q.snoc(i);
+            assert!(!q.working.is_empty());
+            assert!(q.rear_len <= q.front_len);
+        }
+        for i in 0..100 {
+            assert_eq!(Some(&i), q.head());
+            q = q.tail();
+            assert_eq!(q.is_empty(), q.working.is_empty());
+        }
+    }
+
+    #[test]
+    fn rotation_waits_for_the_working_copy() {
+        // The third snoc rotates the rear onto a front of one element,
+        // which is still there in the working copy.
+        let q = queue_of::<PhysicistsQueue<_>>(3);
+        assert!(!q.front.is_forced());
+        assert_eq!(Some(&0), q.head());
+
+        let q = q.tail();
+        assert!(q.front.is_forced());
+        assert_eq!(vec![&1, &2], q.working.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reusing_a_version_shares_its_rotation() {
+        // 63 elements leave the rear empty, as with the banker's queue.
+        let clones = Rc::new(Cell::new(0));
+        let q = (0..63).fold(PhysicistsQueue::new(), |q, _| q.snoc(Counted(clones.clone())));
+
+        clones.set(0);
+        drain(&q);
+        let once = clones.get();
+        assert!(once > 0);
+        for _ in 0..10 {
+            drain(&q);
+        }
+        assert_eq!(once, clones.get());
+    }
+
+    #[test]
+    fn long_run_of_tails_without_overflow() {
+        // After 2^19 - 1 snocs the front has just been rotated, and each
+        // tail until the working copy runs out suspends one more `rest`
+        // of it.
+        let n = (1 << 18) - 1;
+        let suspended = || {
+            let mut q = queue_of::<PhysicistsQueue<_>>(2 * n + 1);
+            for _ in 0..n - 1 {
+                q = q.tail();
+            }
+            q
+        };
+
+        let q = suspended();
+        assert!(!q.front.is_forced());
+        assert_eq!(n - 1, q.pending.len());
+        let q = q.tail();
+        assert!(q.front.is_forced());
+        assert_eq!(Some(&n), q.head());
+
+        drop(suspended());
+    }
+}
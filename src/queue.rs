@@ -0,0 +1,298 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use list::{self, PersistentList, Sequence};
+use shared::{ArcK, RcK, SharedPointerKind};
+
+/// A first-in first-out queue: `snoc` adds to the back while `head` and
+/// `tail` look at and remove the front.
+pub trait Queue {
+    type Item;
+
+    fn empty() -> Self;
+    fn is_empty(&self) -> bool;
+
+    fn snoc(&self, x: Self::Item) -> Self;
+
+    fn head(&self) -> Option<&Self::Item>;
+    fn tail(&self) -> Self;
+}
+
+/// Okasaki's batched queue, from section 5.2.
+///
+/// The front of the queue is one list and the back is another, kept in
+/// reverse, so `snoc` and `tail` are O(1) conses and rests. When the
+/// front runs out the back is reversed to replace it, which makes every
+/// operation O(1) amortized as long as each version is used only once.
+/// Taking the `tail` of the same old version over and over pays for the
+/// reversal each time.
+pub struct BatchedQueue<E, P: SharedPointerKind = RcK> {
+    front: PersistentList<E, P>,
+    rear: PersistentList<E, P>,
+}
+
+impl<E: Clone> BatchedQueue<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone> BatchedQueue<E, ArcK> {
+    pub fn new_sync() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> BatchedQueue<E, P> {
+    /// Keeps the front non-empty whenever the queue is.
+    fn checked(front: PersistentList<E, P>, rear: PersistentList<E, P>) -> Self {
+        if front.is_empty() {
+            BatchedQueue { front: rear.reverse(), rear: front }
+        } else {
+            BatchedQueue { front, rear }
+        }
+    }
+
+    /// Borrows the elements from the front of the queue to the back.
+    pub fn iter(&self) -> Iter<'_, E, P> {
+        Iter { front: self.front.iter(), rear: self.rear.iter().collect() }
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for BatchedQueue<E, P> {
+    fn clone(&self) -> Self {
+        BatchedQueue { front: self.front.clone(), rear: self.rear.clone() }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Queue for BatchedQueue<E, P> {
+    type Item = E;
+
+    fn empty() -> Self {
+        BatchedQueue { front: PersistentList::empty(), rear: PersistentList::empty() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+
+    fn snoc(&self, x: E) -> Self {
+        Self::checked(self.front.clone(), self.rear.cons(x))
+    }
+
+    fn head(&self) -> Option<&E> {
+        self.front.first()
+    }
+
+    fn tail(&self) -> Self {
+        Self::checked(self.front.rest(), self.rear.clone())
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    front: list::Iter<'a, E, P>,
+    /// The back of the queue, last element first.
+    rear: Vec<&'a E>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.front.next().or_else(|| self.rear.pop())
+    }
+}
+
+/// Owning iterator over a `BatchedQueue`, which hands out the elements
+/// by taking the `tail` of the queue one step at a time.
+pub struct IntoIter<E, P: SharedPointerKind> {
+    queue: BatchedQueue<E, P>,
+}
+
+impl<E: Clone, P: SharedPointerKind> Iterator for IntoIter<E, P> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let x = self.queue.head()?.clone();
+        self.queue = self.queue.tail();
+        Some(x)
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for BatchedQueue<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for BatchedQueue<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for BatchedQueue<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, E: Clone, P: SharedPointerKind> IntoIterator for &'a BatchedQueue<E, P> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E, P>;
+
+    fn into_iter(self) -> Iter<'a, E, P> {
+        self.iter()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> IntoIterator for BatchedQueue<E, P> {
+    type Item = E;
+    type IntoIter = IntoIter<E, P>;
+
+    fn into_iter(self) -> IntoIter<E, P> {
+        IntoIter { queue: self }
+    }
+}
+
+/// The first element of the iterator ends up at the head of the queue.
+impl<E: Clone, P: SharedPointerKind> FromIterator<E> for BatchedQueue<E, P> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        Self::checked(iter.into_iter().collect(), PersistentList::empty())
+    }
+}
+
+/// Checks every FIFO in the crate shares, written against `Queue` so
+/// each queue's own tests only cover what is particular to it.
+#[cfg(test)]
+pub(crate) mod checks {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Counts how often it is cloned. Queues only clone an element to
+    /// copy it into a new cell, so the clones an operation makes measure
+    /// how much rotation work it does.
+    #[derive(Debug)]
+    pub(crate) struct Counted(pub(crate) Rc<Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Counted(self.0.clone())
+        }
+    }
+
+    pub(crate) fn queue_of<Q: Queue<Item = usize>>(n: usize) -> Q {
+        (0..n).fold(Q::empty(), |q, x| q.snoc(x))
+    }
+
+    /// Takes the `tail` of `queue` until it is empty.
+    pub(crate) fn drain<Q: Queue>(queue: &Q) {
+        let mut q = queue.tail();
+        while !q.is_empty() {
+            q = q.tail();
+        }
+    }
+
+    /// Fills a queue with `n` elements, then empties it again, and
+    /// returns the most clones any single `snoc` or `tail` made.
+    pub(crate) fn most_work_per_operation<Q: Queue<Item = Counted>>(n: usize) -> usize {
+        let clones = Rc::new(Cell::new(0));
+        let mut most = 0;
+        let mut q = Q::empty();
+        for _ in 0..n {
+            let x = Counted(clones.clone());
+            clones.set(0);
+            q = q.snoc(x);
+            most = most.max(clones.get());
+        }
+        while !q.is_empty() {
+            clones.set(0);
+            q = q.tail();
+            most = most.max(clones.get());
+        }
+        most
+    }
+
+    /// Order, persistence and the std conversions, up to a queue of a
+    /// million elements.
+    pub(crate) fn check_fifo<Q>()
+    where Q: Queue<Item = usize> + PartialEq + fmt::Debug + FromIterator<usize> + IntoIterator<Item = usize>,
+          for<'a> &'a Q: IntoIterator<Item = &'a usize>,
+    {
+        let empty = Q::empty();
+        assert!(empty.is_empty());
+        assert_eq!(None, empty.head());
+        assert!(empty.tail().is_empty());
+
+        let mut q = queue_of::<Q>(100);
+        for i in 0..100 {
+            assert_eq!(Some(&i), q.head());
+            assert_eq!(100 - i, (&q).into_iter().count());
+            q = q.tail();
+        }
+        assert!(q.is_empty());
+
+        let mut q = Q::empty();
+        for i in 0..100 {
+            q = q.snoc(2 * i).snoc(2 * i + 1);
+            assert_eq!(Some(&i), q.head());
+            q = q.tail();
+        }
+        assert_eq!((100..200).collect::<Vec<_>>(), q.into_iter().collect::<Vec<_>>());
+
+        let q = queue_of::<Q>(3);
+        let longer = q.snoc(3);
+        let shorter = q.tail();
+        assert_eq!(vec![&0, &1, &2], (&q).into_iter().collect::<Vec<_>>());
+        assert_eq!(vec![&0, &1, &2, &3], (&longer).into_iter().collect::<Vec<_>>());
+        assert_eq!(vec![&1, &2], (&shorter).into_iter().collect::<Vec<_>>());
+
+        let q = (0..5).collect::<Q>();
+        assert_eq!(queue_of::<Q>(5), q);
+        assert_eq!("[1, 2, 3, 4, 5]", format!("{:?}", q.snoc(5).tail()));
+        assert_eq!(10, (&q).into_iter().sum::<usize>());
+        assert!(Vec::new().into_iter().collect::<Q>().is_empty());
+
+        let q = queue_of::<Q>(1_000_000);
+        assert_eq!(499_999_500_000, q.into_iter().sum::<usize>());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use super::checks::{self, queue_of};
+
+    #[test]
+    fn behaves_as_a_fifo() {
+        checks::check_fifo::<BatchedQueue<usize>>();
+    }
+
+    #[test]
+    fn iter_crosses_from_front_to_rear() {
+        let q = queue_of::<BatchedQueue<usize>>(4).tail().snoc(4).snoc(5);
+        assert_eq!(vec![&1, &2, &3, &4, &5], q.iter().collect::<Vec<_>>());
+        assert_eq!("[1, 2, 3, 4, 5]", format!("{:?}", q));
+    }
+
+    #[test]
+    fn sync_queue_is_shared_across_threads() {
+        let q = (0..100).fold(BatchedQueue::new_sync(), |q, x| q.snoc(x));
+        let shared = q.clone();
+        let sum = thread::spawn(move || shared.into_iter().sum::<i32>()).join().unwrap();
+        assert_eq!(4950, sum);
+        assert_eq!(Some(&0), q.head());
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+use list::{PersistentList, Sequence};
+use queue::Queue;
+use stream::{self, Stream};
+
+/// Okasaki's real-time queue, from section 7.2.
+///
+/// The front is a stream and the rear a reversed list, as in the
+/// banker's queue, but the rotation that moves the rear onto the front
+/// is written so that each cell of the result takes O(1) work to force,
+/// and the queue keeps a schedule: the part of the front not forced yet.
+/// Every operation forces one cell of the schedule, so by the time the
+/// rear outgrows the front the previous rotation has been fully
+/// evaluated and a new one can begin. `snoc`, `head` and `tail` are
+/// O(1) in the worst case, not just amortized. Being made of streams,
+/// it can't be shared between threads.
+pub struct RealTimeQueue<E: 'static> {
+    front: Stream<E>,
+    /// The back of the queue, last element first.
+    rear: PersistentList<E>,
+    /// The unforced suffix of `front`, as long as the front is longer
+    /// than the rear.
+    schedule: Stream<E>,
+}
+
+/// Lazily computes `front ++ reverse rear ++ acc`, given that the rear
+/// is one element longer than the front. Each cell pulls one element
+/// off both lists, so forcing it is O(1).
+fn rotate<E>(front: Stream<E>, rear: PersistentList<E>, acc: Stream<E>) -> Stream<E>
+where E: Clone + 'static,
+{
+    Stream::lazy(move || {
+        let y = rear.first().expect("a rotation's rear is longer than its front").clone();
+        match front.first() {
+            None => Some((y, acc)),
+            Some(x) => Some((x.clone(), rotate(front.rest(), rear.rest(), acc.cons(y)))),
+        }
+    })
+}
+
+impl<E: Clone + 'static> RealTimeQueue<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+
+    /// Forces the next cell of the schedule, or starts a new rotation
+    /// once the schedule has run out, which happens exactly when the
+    /// rear has grown one longer than the front.
+    fn exec(front: Stream<E>, rear: PersistentList<E>, schedule: Stream<E>) -> Self {
+        if schedule.is_empty() {
+            let front = rotate(front, rear, Stream::empty());
+            RealTimeQueue { front: front.clone(), rear: PersistentList::empty(), schedule: front }
+        } else {
+            RealTimeQueue { front, rear, schedule: schedule.rest() }
+        }
+    }
+
+    /// Borrows the elements from the front of the queue to the back,
+    /// forcing the rest of the front as it goes.
+    pub fn iter(&self) -> Iter<'_, E> {
+        Iter { front: self.front.iter(), rear: self.rear.iter().collect() }
+    }
+}
+
+impl<E> Clone for RealTimeQueue<E> {
+    fn clone(&self) -> Self {
+        RealTimeQueue {
+            front: self.front.clone(),
+            rear: self.rear.clone(),
+            schedule: self.schedule.clone(),
+        }
+    }
+}
+
+impl<E: Clone + 'static> Queue for RealTimeQueue<E> {
+    type Item = E;
+
+    fn empty() -> Self {
+        RealTimeQueue {
+            front: Stream::empty(),
+            rear: PersistentList::empty(),
+            schedule: Stream::empty(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+
+    fn snoc(&self, x: E) -> Self {
+        Self::exec(self.front.clone(), self.rear.cons(x), self.schedule.clone())
+    }
+
+    fn head(&self) -> Option<&E> {
+        self.front.first()
+    }
+
+    fn tail(&self) -> Self {
+        if self.is_empty() {
+            return self.clone();
+        }
+        Self::exec(self.front.rest(), self.rear.clone(), self.schedule.clone())
+    }
+}
+
+pub struct Iter<'a, E: 'static> {
+    front: stream::Iter<'a, E>,
+    /// The back of the queue, last element first.
+    rear: Vec<&'a E>,
+}
+
+impl<'a, E> Iterator for Iter<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.front.next().or_else(|| self.rear.pop())
+    }
+}
+
+/// Owning iterator over a `RealTimeQueue`, which hands out the elements
+/// by taking the `tail` of the queue one step at a time.
+pub struct IntoIter<E: 'static> {
+    queue: RealTimeQueue<E>,
+}
+
+impl<E: Clone + 'static> Iterator for IntoIter<E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let x = self.queue.head()?.clone();
+        self.queue = self.queue.tail();
+        Some(x)
+    }
+}
+
+impl<E: Clone + 'static> Default for RealTimeQueue<E> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone + fmt::Debug + 'static> fmt::Debug for RealTimeQueue<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E: Clone + PartialEq + 'static> PartialEq for RealTimeQueue<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, E: Clone + 'static> IntoIterator for &'a RealTimeQueue<E> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E>;
+
+    fn into_iter(self) -> Iter<'a, E> {
+        self.iter()
+    }
+}
+
+impl<E: Clone + 'static> IntoIterator for RealTimeQueue<E> {
+    type Item = E;
+    type IntoIter = IntoIter<E>;
+
+    fn into_iter(self) -> IntoIter<E> {
+        IntoIter { queue: self }
+    }
+}
+
+/// The first element of the iterator ends up at the head of the queue.
+/// The whole front is built up front, so the schedule starts out forced.
+impl<E: Clone + 'static> FromIterator<E> for RealTimeQueue<E> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        let elements = iter.into_iter().collect::<Vec<_>>();
+        let front = elements.into_iter().rev().fold(Stream::empty(), |s, x| s.cons(x));
+        RealTimeQueue { front: front.clone(), rear: PersistentList::empty(), schedule: front }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bankers_queue::BankersQueue;
+    use queue::checks::{self, most_work_per_operation};
+
+    #[test]
+    fn behaves_as_a_fifo() {
+        checks::check_fifo::<RealTimeQueue<usize>>();
+    }
+
+    #[test]
+    fn schedule_is_front_minus_rear() {
+        let mut q = RealTimeQueue::new();
+        for i in 0..100 {
+            q = q.snoc(i);
+            let (front, rear) = (q.front.iter().count(), q.rear.len());
+            assert_eq!(front - rear, q.schedule.iter().count());
+        }
+    }
+
+    #[test]
+    fn every_operation_does_constant_work() {
+        // The banker's queue does a whole reversal in a single `tail`.
+        assert!(most_work_per_operation::<BankersQueue<_>>(1000) > 400);
+
+        for &n in &[10, 1000, 10_000] {
+            assert!(most_work_per_operation::<RealTimeQueue<_>>(n) <= 2);
+        }
+    }
+}
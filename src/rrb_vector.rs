@@ -0,0 +1,774 @@
+use std::{cmp, fmt, slice};
+use std::iter::FromIterator;
+use std::ops::Index;
+
+use list::{IndexOutOfRange, Sequence};
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+
+/// How many more nodes than the optimum a concatenation may leave
+/// along its seam before it redistributes their contents.
+const EXTRA_STEPS: usize = 2;
+
+/// Elements below a full node of the given height, leaves being at 0.
+fn capacity(height: usize) -> usize {
+    1 << (BITS * (height + 1))
+}
+
+type NodePointer<E, P> = SharedPointer<Node<E, P>, P>;
+
+enum Node<E, P: SharedPointerKind> {
+    Leaf(Vec<E>),
+    /// The number of elements below the node, then the running totals
+    /// for its children. Those are left out when every child but the
+    /// last is full, since the right child can then be worked out from
+    /// the index alone.
+    Branch(usize, Option<Vec<usize>>, Vec<NodePointer<E, P>>),
+}
+
+impl<E: Clone, P: SharedPointerKind> Node<E, P> {
+    fn size(&self) -> usize {
+        match *self {
+            Node::Leaf(ref xs) => xs.len(),
+            Node::Branch(size, _, _) => size,
+        }
+    }
+
+    /// Leaf elements or branch children.
+    fn slots(&self) -> usize {
+        match *self {
+            Node::Leaf(ref xs) => xs.len(),
+            Node::Branch(_, _, ref children) => children.len(),
+        }
+    }
+
+    fn children(&self) -> &[NodePointer<E, P>] {
+        match *self {
+            Node::Branch(_, _, ref children) => children,
+            Node::Leaf(_) => unreachable!("leaves have no children"),
+        }
+    }
+
+    fn branch(height: usize, children: Vec<NodePointer<E, P>>) -> NodePointer<E, P> {
+        let full = capacity(height - 1);
+        let mut size = 0;
+        let mut sizes = Vec::with_capacity(children.len());
+        for child in &children {
+            size += child.size();
+            sizes.push(size);
+        }
+        let dense = children[..children.len() - 1].iter().all(|child| child.size() == full);
+        P::new(Node::Branch(size, if dense { None } else { Some(sizes) }, children))
+    }
+
+    /// Wraps a leaf in single child branches up to the given height.
+    fn path(height: usize, leaf: NodePointer<E, P>) -> NodePointer<E, P> {
+        (1..=height).fold(leaf, |node, height| Self::branch(height, vec![node]))
+    }
+
+    /// Which child of a branch holds `index`, and how many elements
+    /// come before that child.
+    fn child_index(&self, height: usize, index: usize) -> (usize, usize) {
+        let full = capacity(height - 1);
+        match *self {
+            Node::Branch(_, None, _) => (index / full, index / full * full),
+            Node::Branch(_, Some(ref sizes), _) => {
+                // No child holds more than `full` elements, so the
+                // radix guess is never past the right child.
+                let mut i = index / full;
+                while sizes[i] <= index {
+                    i += 1;
+                }
+                (i, if i == 0 { 0 } else { sizes[i - 1] })
+            },
+            Node::Leaf(_) => unreachable!("leaves have no children"),
+        }
+    }
+
+    fn get(&self, height: usize, index: usize) -> &E {
+        let (mut node, mut height, mut index) = (self, height, index);
+        loop {
+            match *node {
+                Node::Leaf(ref xs) => return &xs[index],
+                Node::Branch(_, _, ref children) => {
+                    let (i, before) = node.child_index(height, index);
+                    node = &children[i];
+                    height -= 1;
+                    index -= before;
+                },
+            }
+        }
+    }
+
+    fn set(&self, height: usize, index: usize, val: E) -> NodePointer<E, P> {
+        match *self {
+            Node::Leaf(ref xs) => {
+                let mut xs = xs.clone();
+                xs[index] = val;
+                P::new(Node::Leaf(xs))
+            },
+            Node::Branch(size, ref sizes, ref children) => {
+                let (i, before) = self.child_index(height, index);
+                let mut children = children.clone();
+                children[i] = children[i].set(height - 1, index - before, val);
+                P::new(Node::Branch(size, sizes.clone(), children))
+            },
+        }
+    }
+
+    /// Adds a leaf after the last one, or returns `None` if there is no
+    /// room left below this node.
+    fn push_leaf(&self, height: usize, leaf: NodePointer<E, P>) -> Option<NodePointer<E, P>> {
+        let children = self.children();
+        let last = children.last().expect("branches are never empty");
+        let pushed = if height == 1 { None } else { last.push_leaf(height - 1, leaf.clone()) };
+        let mut children = children.to_vec();
+        match pushed {
+            Some(last) => *children.last_mut().unwrap() = last,
+            None if children.len() < WIDTH => children.push(Self::path(height - 1, leaf)),
+            None => return None,
+        }
+        Some(Self::branch(height, children))
+    }
+
+    /// The first `n` elements below `node`, for `0 < n <= size`.
+    fn take(node: &NodePointer<E, P>, height: usize, n: usize) -> NodePointer<E, P> {
+        if n == node.size() {
+            return node.clone();
+        }
+        match **node {
+            Node::Leaf(ref xs) => P::new(Node::Leaf(xs[..n].to_vec())),
+            Node::Branch(_, _, ref children) => {
+                let (i, before) = node.child_index(height, n - 1);
+                let mut kept = children[..i].to_vec();
+                kept.push(Self::take(&children[i], height - 1, n - before));
+                Self::branch(height, kept)
+            },
+        }
+    }
+
+    /// Everything after the first `n` elements below `node`, for
+    /// `0 <= n < size`.
+    fn drop(node: &NodePointer<E, P>, height: usize, n: usize) -> NodePointer<E, P> {
+        if n == 0 {
+            return node.clone();
+        }
+        match **node {
+            Node::Leaf(ref xs) => P::new(Node::Leaf(xs[n..].to_vec())),
+            Node::Branch(_, _, ref children) => {
+                let (i, before) = node.child_index(height, n);
+                let mut kept = vec![Self::drop(&children[i], height - 1, n - before)];
+                kept.extend(children[i + 1..].iter().cloned());
+                Self::branch(height, kept)
+            },
+        }
+    }
+
+    /// Joins two trees into one or two nodes as tall as the taller
+    /// tree, rebalancing the nodes along the seam on the way back up.
+    fn merge(left: &NodePointer<E, P>, left_height: usize,
+             right: &NodePointer<E, P>, right_height: usize) -> Vec<NodePointer<E, P>> {
+        if left_height == 0 && right_height == 0 {
+            return Self::rebalance(0, vec![left.clone(), right.clone()]);
+        }
+
+        let height = cmp::max(left_height, right_height);
+        let mut nodes = Vec::new();
+        let middle = if left_height > right_height {
+            let (last, init) = left.children().split_last().unwrap();
+            nodes.extend(init.iter().cloned());
+            Self::merge(last, left_height - 1, right, right_height)
+        } else if left_height < right_height {
+            Self::merge(left, left_height, &right.children()[0], right_height - 1)
+        } else {
+            let (last, init) = left.children().split_last().unwrap();
+            nodes.extend(init.iter().cloned());
+            Self::merge(last, left_height - 1, &right.children()[0], right_height - 1)
+        };
+        nodes.extend(middle);
+        if right_height == height {
+            nodes.extend(right.children()[1..].iter().cloned());
+        }
+
+        Self::rebalance(height - 1, nodes)
+            .chunks(WIDTH)
+            .map(|children| Self::branch(height, children.to_vec()))
+            .collect()
+    }
+
+    /// Packs the contents of `nodes` into fewer nodes when there are
+    /// more than `EXTRA_STEPS` too many of them, keeping every node that
+    /// does not have to change.
+    fn rebalance(height: usize, nodes: Vec<NodePointer<E, P>>) -> Vec<NodePointer<E, P>> {
+        let counts = nodes.iter().map(|node| node.slots()).collect::<Vec<_>>();
+        let plan = plan(&counts);
+        if plan == counts {
+            return nodes;
+        }
+
+        let mut rebalanced = Vec::with_capacity(plan.len());
+        let (mut start, mut original, mut original_start) = (0, 0, 0);
+        for count in plan {
+            while original_start < start {
+                original_start += counts[original];
+                original += 1;
+            }
+            if original_start == start && counts.get(original) == Some(&count) {
+                rebalanced.push(nodes[original].clone());
+            } else if height == 0 {
+                let slices = nodes.iter().map(|node| match **node {
+                    Node::Leaf(ref xs) => &xs[..],
+                    Node::Branch(..) => unreachable!("height 0 nodes are leaves"),
+                });
+                rebalanced.push(P::new(Node::Leaf(gather(slices, start, count))));
+            } else {
+                let slices = nodes.iter().map(|node| node.children());
+                rebalanced.push(Self::branch(height, gather(slices, start, count)));
+            }
+            start += count;
+        }
+        rebalanced
+    }
+}
+
+/// Bagwell and Rompf's concatenation plan: while there are too many
+/// nodes, spread the contents of the first sparse one over the nodes
+/// after it.
+fn plan(counts: &[usize]) -> Vec<usize> {
+    let mut counts = counts.to_vec();
+    let total = counts.iter().sum::<usize>();
+    let optimal = total.div_ceil(WIDTH);
+    let mut i = 0;
+    while counts.len() > optimal + EXTRA_STEPS {
+        while counts[i] >= WIDTH - EXTRA_STEPS / 2 {
+            i += 1;
+        }
+        if i + 1 == counts.len() {
+            break;
+        }
+        let mut remaining = counts[i];
+        while remaining > 0 {
+            let next = counts[i + 1];
+            counts[i] = cmp::min(remaining + next, WIDTH);
+            remaining = remaining + next - counts[i];
+            i += 1;
+        }
+        counts.remove(i);
+        i -= 1;
+    }
+    counts
+}
+
+/// Copies `count` items starting at `start` out of the concatenation
+/// of `slices`.
+fn gather<'a, I, S>(slices: S, start: usize, count: usize) -> Vec<I>
+where I: Clone + 'a,
+      S: Iterator<Item = &'a [I]>,
+{
+    let mut items = Vec::with_capacity(count);
+    let mut offset = 0;
+    for slice in slices {
+        let end = offset + slice.len();
+        if end > start && offset < start + count {
+            let from = cmp::max(start, offset) - offset;
+            let to = cmp::min(start + count, end) - offset;
+            items.extend_from_slice(&slice[from..to]);
+        }
+        offset = end;
+    }
+    items
+}
+
+/// A relaxed radix balanced vector, after Bagwell and Rompf.
+///
+/// Elements live in the leaves of a trie with up to 32 children per
+/// node, so `get` and `set` only touch O(log32 n) nodes. The last few
+/// elements are kept in a separate tail, which makes `push_back` O(1)
+/// amortized. Concatenating or splitting vectors leaves nodes that are
+/// not full; those keep a table of child sizes, which is enough to keep
+/// `concat` and `split_at` O(log n).
+pub struct RrbVector<E, P: SharedPointerKind = RcK> {
+    len: usize,
+    height: usize,
+    root: NodePointer<E, P>,
+    tail: SharedPointer<Vec<E>, P>,
+}
+
+impl<E: Clone> RrbVector<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone> RrbVector<E, ArcK> {
+    pub fn new_sync() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> RrbVector<E, P> {
+    fn tail_offset(&self) -> usize {
+        self.len - self.tail.len()
+    }
+
+    /// Builds a vector with the given tree, dropping any branches with
+    /// a single child from the top of it.
+    fn with_tree(len: usize, height: usize, root: NodePointer<E, P>, tail: SharedPointer<Vec<E>, P>) -> Self {
+        let (mut height, mut root) = (height, root);
+        while height > 0 && root.slots() == 1 {
+            root = root.children()[0].clone();
+            height -= 1;
+        }
+        RrbVector { len, height, root, tail }
+    }
+
+    fn push_leaf(&self, leaf: NodePointer<E, P>) -> (NodePointer<E, P>, usize) {
+        if self.tail_offset() == 0 {
+            return (leaf, 0);
+        }
+        if self.height == 0 {
+            return (Node::<E, P>::branch(1, vec![self.root.clone(), leaf]), 1);
+        }
+        match self.root.push_leaf(self.height, leaf.clone()) {
+            Some(root) => (root, self.height),
+            None => {
+                let path = Node::<E, P>::path(self.height, leaf);
+                (Node::<E, P>::branch(self.height + 1, vec![self.root.clone(), path]), self.height + 1)
+            },
+        }
+    }
+
+    /// Moves the tail into the tree, so that every element is in it.
+    fn flush_tail(&self) -> Self {
+        if self.tail.is_empty() {
+            return self.clone();
+        }
+        let (root, height) = self.push_leaf(P::new(Node::Leaf((*self.tail).clone())));
+        RrbVector { len: self.len, height, root, tail: P::new(Vec::new()) }
+    }
+
+    pub fn set(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        if index >= self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        let offset = self.tail_offset();
+        let mut updated = self.clone();
+        if index >= offset {
+            let mut tail = (*self.tail).clone();
+            tail[index - offset] = val;
+            updated.tail = P::new(tail);
+        } else {
+            updated.root = self.root.set(self.height, index, val);
+        }
+        Ok(updated)
+    }
+
+    pub fn push_back(&self, val: E) -> Self {
+        if self.tail.len() < WIDTH {
+            let mut tail = (*self.tail).clone();
+            tail.push(val);
+            return RrbVector { len: self.len + 1, tail: P::new(tail), ..self.clone() };
+        }
+        let (root, height) = self.push_leaf(P::new(Node::Leaf((*self.tail).clone())));
+        RrbVector { len: self.len + 1, height, root, tail: P::new(vec![val]) }
+    }
+
+    /// Splits the vector before `index`, or at its end if `index` is
+    /// past it.
+    fn split(&self, index: usize) -> (Self, Self) {
+        if index == 0 {
+            return (Self::empty(), self.clone());
+        }
+        if index >= self.len {
+            return (self.clone(), Self::empty());
+        }
+
+        let offset = self.tail_offset();
+        if index >= offset {
+            let (front, back) = self.tail.split_at(index - offset);
+            let left = RrbVector { len: index, tail: P::new(front.to_vec()), ..self.clone() };
+            let right = RrbVector { len: self.len - index, tail: P::new(back.to_vec()), ..Self::empty() };
+            return (left, right);
+        }
+
+        let left = Node::<E, P>::take(&self.root, self.height, index);
+        let right = Node::<E, P>::drop(&self.root, self.height, index);
+        (Self::with_tree(index, self.height, left, P::new(Vec::new())),
+         Self::with_tree(self.len - index, self.height, right, self.tail.clone()))
+    }
+
+    pub fn iter(&self) -> Iter<'_, E, P> {
+        let pending = if self.tail_offset() == 0 { Vec::new() } else { vec![&*self.root] };
+        Iter { pending, leaf: [].iter(), tail: self.tail.iter() }
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for RrbVector<E, P> {
+    fn clone(&self) -> Self {
+        RrbVector {
+            len: self.len,
+            height: self.height,
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for RrbVector<E, P> {
+    type Iter<'a> = Iter<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        RrbVector {
+            len: 0,
+            height: 0,
+            root: P::new(Node::Leaf(Vec::new())),
+            tail: P::new(Vec::new()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn cons(&self, el: E) -> Self {
+        Self::empty().push_back(el).concat(self)
+    }
+
+    fn first(&self) -> Option<&E> {
+        self.get(0).ok()
+    }
+
+    fn rest(&self) -> Self {
+        self.split(1).1
+    }
+
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        self.set(index, val)
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        if self.is_empty() {
+            return other.clone();
+        }
+        if other.tail_offset() == 0 {
+            return other.tail.iter().fold(self.clone(), |v, x| v.push_back(x.clone()));
+        }
+
+        let left = self.flush_tail();
+        let mut nodes = Node::<E, P>::merge(&left.root, left.height, &other.root, other.height);
+        let mut height = cmp::max(left.height, other.height);
+        let root = if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            height += 1;
+            Node::<E, P>::branch(height, nodes)
+        };
+        Self::with_tree(self.len + other.len, height, root, other.tail.clone())
+    }
+
+    fn iter(&self) -> Iter<'_, E, P> {
+        RrbVector::iter(self)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.get(index).ok()
+    }
+
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        if index >= self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        let offset = self.tail_offset();
+        if index >= offset {
+            Ok(&self.tail[index - offset])
+        } else {
+            Ok(self.root.get(self.height, index))
+        }
+    }
+
+    fn split_at(&self, index: usize) -> Result<(Self, Self), IndexOutOfRange> {
+        if index > self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        Ok(self.split(index))
+    }
+
+    fn insert_at(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let (left, right) = self.split_at(index)?;
+        Ok(left.push_back(val).concat(&right))
+    }
+
+    fn remove_at(&self, index: usize) -> Result<Self, IndexOutOfRange> {
+        if index >= self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        let (left, right) = self.split(index);
+        Ok(left.concat(&right.split(1).1))
+    }
+
+    fn last(&self) -> Option<&E> {
+        self.len.checked_sub(1).and_then(|index| self.nth(index))
+    }
+
+    fn take(&self, n: usize) -> Self {
+        self.split(n).0
+    }
+
+    fn drop(&self, n: usize) -> Self {
+        self.split(n).1
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    pending: Vec<&'a Node<E, P>>,
+    leaf: slice::Iter<'a, E>,
+    tail: slice::Iter<'a, E>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        loop {
+            if let Some(x) = self.leaf.next() {
+                return Some(x);
+            }
+            match self.pending.pop() {
+                Some(Node::Leaf(xs)) => self.leaf = xs.iter(),
+                Some(Node::Branch(_, _, children)) => {
+                    self.pending.extend(children.iter().rev().map(|child| &**child));
+                },
+                None => return self.tail.next(),
+            }
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for RrbVector<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for RrbVector<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for RrbVector<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<E, P> Index<usize> for RrbVector<E, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    type Output = E;
+
+    fn index(&self, index: usize) -> &E {
+        match self.get(index) {
+            Ok(x) => x,
+            Err(_) => panic!("index out of bounds: the len is {} but the index is {}", self.len, index),
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> FromIterator<E> for RrbVector<E, P> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::empty(), |v, x| v.push_back(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    fn vector_of(range: ::std::ops::Range<usize>) -> RrbVector<usize> {
+        range.collect()
+    }
+
+    fn elements(v: &RrbVector<usize>) -> Vec<usize> {
+        v.iter().cloned().collect()
+    }
+
+    /// Checks the size tables and heights of a tree, returning its size.
+    fn check_node(node: &Node<usize, RcK>, height: usize) -> usize {
+        match *node {
+            Node::Leaf(ref xs) => {
+                assert_eq!(0, height);
+                assert!(xs.len() <= WIDTH);
+                xs.len()
+            },
+            Node::Branch(size, ref sizes, ref children) => {
+                assert!(height > 0);
+                assert!(!children.is_empty() && children.len() <= WIDTH);
+                let mut total = 0;
+                for (i, child) in children.iter().enumerate() {
+                    let child_size = check_node(child, height - 1);
+                    assert!(child_size > 0);
+                    total += child_size;
+                    match *sizes {
+                        Some(ref sizes) => assert_eq!(total, sizes[i]),
+                        None if i + 1 < children.len() => assert_eq!(capacity(height - 1), child_size),
+                        None => {},
+                    }
+                }
+                assert_eq!(size, total);
+                total
+            },
+        }
+    }
+
+    fn check(v: &RrbVector<usize>) {
+        if v.tail_offset() > 0 {
+            assert_eq!(v.tail_offset(), check_node(&v.root, v.height));
+        }
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let v: RrbVector<u8> = RrbVector::new();
+        assert_eq!(v.first(), None);
+        assert!(v.rest().is_empty());
+        assert!(v.get(0).is_err());
+    }
+
+    #[test]
+    fn push_back_then_get_every_index() {
+        let v = vector_of(0..30_000);
+        check(&v);
+        assert_eq!(2, v.height);
+        for i in 0..30_000 {
+            assert_eq!(Ok(&i), v.get(i).map_err(|_| ()));
+        }
+        assert!(v.get(30_000).is_err());
+        assert_eq!((0..30_000).collect::<Vec<_>>(), elements(&v));
+    }
+
+    #[test]
+    fn set_copies_one_path() {
+        let v = vector_of(0..100);
+        let new_v = v.set(5, 500).unwrap();
+        assert_eq!(Ok(&500), new_v.get(5).map_err(|_| ()));
+        assert_eq!(Ok(&5), v.get(5).map_err(|_| ()));
+        assert!(Rc::ptr_eq(&v.tail, &new_v.tail));
+        assert!(Rc::ptr_eq(&v.root.children()[1], &new_v.root.children()[1]));
+        assert!(!Rc::ptr_eq(&v.root.children()[0], &new_v.root.children()[0]));
+
+        let new_v = v.set(99, 0).unwrap();
+        assert!(Rc::ptr_eq(&v.root, &new_v.root));
+        assert!(v.set(100, 0).is_err());
+    }
+
+    #[test]
+    fn cons_and_rest() {
+        let mut v = vector_of(0..100).cons(1000);
+        assert_eq!(Some(&1000), v.first());
+        v = v.rest();
+        for i in 0..100 {
+            assert_eq!(Some(&i), v.first());
+            assert_eq!(100 - i, v.len());
+            check(&v);
+            v = v.rest();
+        }
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn concat_vectors_of_many_sizes() {
+        let sizes = [0, 1, 31, 32, 33, 100, 1024, 1057, 5000];
+        for &n in &sizes {
+            for &m in &sizes {
+                let joined = vector_of(0..n).concat(&vector_of(n..n + m));
+                check(&joined);
+                assert_eq!(n + m, joined.len());
+                assert_eq!((0..n + m).collect::<Vec<_>>(), elements(&joined));
+                for i in (0..n + m).step_by(7) {
+                    assert_eq!(Some(&i), joined.nth(i));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_concats_stay_shallow() {
+        let mut v = RrbVector::new();
+        let mut expected = Vec::new();
+        for i in 0..300 {
+            let chunk = vector_of(expected.len()..expected.len() + i % 45 + 1);
+            expected.extend(chunk.iter().cloned());
+            v = v.concat(&chunk);
+        }
+        check(&v);
+        assert!(v.height <= 3);
+        assert_eq!(expected, elements(&v));
+        for (i, x) in expected.iter().enumerate() {
+            assert_eq!(x, &v[i]);
+        }
+    }
+
+    #[test]
+    fn split_at_every_index() {
+        let v = vector_of(0..300).concat(&vector_of(300..350)).concat(&vector_of(350..1100));
+        for i in 0..=1100 {
+            let (left, right) = v.split_at(i).unwrap();
+            check(&left);
+            check(&right);
+            assert_eq!((0..i).collect::<Vec<_>>(), elements(&left));
+            assert_eq!((i..1100).collect::<Vec<_>>(), elements(&right));
+            if i % 50 == 0 {
+                assert_eq!(v, left.concat(&right));
+            }
+        }
+    }
+
+    #[test]
+    fn insert_and_remove_at() {
+        let v = vector_of(0..2000);
+        let inserted = v.insert_at(1500, 9999).unwrap();
+        check(&inserted);
+        assert_eq!(Ok(&9999), inserted.get(1500));
+        assert_eq!(Ok(&1500), inserted.get(1501));
+
+        let removed = inserted.remove_at(1500).unwrap();
+        check(&removed);
+        assert_eq!(v, removed);
+
+        assert_eq!(Err(IndexOutOfRange { index: 2001, len: 2000 }), v.insert_at(2001, 0));
+        assert_eq!(Err(IndexOutOfRange { index: 2000, len: 2000 }), v.remove_at(2000));
+    }
+
+    #[test]
+    fn concat_leaves_both_vectors_usable() {
+        let xs = vector_of(0..50);
+        let ys = vector_of(50..100);
+        let _zs = xs.concat(&ys);
+        assert_eq!((0..50).collect::<Vec<_>>(), elements(&xs));
+        assert_eq!((50..100).collect::<Vec<_>>(), elements(&ys));
+    }
+
+    #[test]
+    fn plan_packs_sparse_nodes() {
+        assert_eq!(vec![32, 32], plan(&[32, 32]));
+        assert_eq!(vec![1, 1], plan(&[1, 1]));
+        assert_eq!(vec![10, 5, 5], plan(&[5, 5, 5, 5]));
+        assert_eq!(vec![32, 32, 28, 30, 31, 3, 2], plan(&[32, 10, 30, 20, 30, 31, 3, 2]));
+    }
+}
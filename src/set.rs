@@ -1,10 +1,53 @@
+use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
 
-use tree::Tree;
-use util::iterate;
+use shared::{RcK, SharedPointer, SharedPointerKind};
+use sharing::{allocation_bytes, SharedNodes};
+use tree::{self, Tree};
 
+#[derive(Debug)]
 pub struct AlreadyPresent;
 
+/// Builds an `UnbalancedSet` from the given elements.
+///
+/// ```
+/// # #[macro_use] extern crate functional_data_structures;
+/// # use functional_data_structures::prelude::*;
+/// # fn main() {
+/// let s = set![2, 1, 3];
+/// assert!(s.member(&1));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! set {
+    ($($x:expr),* $(,)?) => {{
+        let set = <$crate::UnbalancedSet<_> as $crate::Set<_>>::empty();
+        $(let set = $crate::Set::insert(&set, $x);)*
+        set
+    }};
+}
+
+/// Builds an `UnbalancedMap` from `key => value` pairs. As with
+/// `FiniteMap::bind`, the first binding of a repeated key wins.
+///
+/// ```
+/// # #[macro_use] extern crate functional_data_structures;
+/// # use functional_data_structures::prelude::*;
+/// # fn main() {
+/// let m = map!{"one" => 1, "two" => 2};
+/// assert_eq!(Some(&2), m.lookup(&"two"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! map {
+    ($($k:expr => $v:expr),* $(,)?) => {{
+        let map = <$crate::UnbalancedMap<(_, _)> as $crate::FiniteMap>::empty();
+        $(let map = $crate::FiniteMap::bind(&map, $k, $v);)*
+        map
+    }};
+}
+
 pub trait Set<E>
 where E: Clone + PartialOrd
 {
@@ -13,13 +56,29 @@ where E: Clone + PartialOrd
     fn insert(&self, val: E) -> Self;
 }
 
-#[derive(Clone, Debug)]
-struct UnbalancedSet<T>(UnbalancedMap<Rc<T>>);
+pub struct UnbalancedSet<T, P: SharedPointerKind = RcK>(UnbalancedMap<SharedPointer<T, P>, P>);
+
+impl<T, P: SharedPointerKind> Clone for UnbalancedSet<T, P> {
+    fn clone(&self) -> Self {
+        UnbalancedSet(self.0.clone())
+    }
+}
+
+impl<T, P> fmt::Debug for UnbalancedSet<T, P>
+where P: SharedPointerKind,
+      SharedPointer<T, P>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("UnbalancedSet").field(&*(self.0).0).finish()
+    }
+}
 
-impl<T> Set<T> for UnbalancedSet<T>
-where T: Clone + PartialOrd
+impl<T, P> Set<T> for UnbalancedSet<T, P>
+where T: Clone + PartialOrd,
+      P: SharedPointerKind,
+      SharedPointer<T, P>: MapEntry<Key = T, Value = T>,
 {
-    fn empty() -> UnbalancedSet<T> {
+    fn empty() -> UnbalancedSet<T, P> {
         UnbalancedSet(UnbalancedMap::empty())
     }
 
@@ -27,14 +86,28 @@ where T: Clone + PartialOrd
         self.0.lookup(x).is_some()
     }
 
-    fn insert(&self, val: T) -> UnbalancedSet<T> {
-        match self.0.insert(Rc::new(val)) {
+    fn insert(&self, val: T) -> UnbalancedSet<T, P> {
+        match self.0.insert(P::new(val)) {
             Ok(m) => UnbalancedSet(m),
             Err(AlreadyPresent) => self.clone(),
         }
     }
 }
 
+/// Counts the nodes of the underlying tree; the separately allocated
+/// elements are not included.
+impl<T, P: SharedPointerKind> SharedNodes for UnbalancedSet<T, P> {
+    fn node_bytes() -> usize {
+        UnbalancedMap::<SharedPointer<T, P>, P>::node_bytes()
+    }
+
+    fn visit_nodes<F>(&self, visit: F)
+    where F: FnMut(usize) -> bool,
+    {
+        self.0.visit_nodes(visit)
+    }
+}
+
 pub trait MapEntry: Clone {
     type Key: PartialOrd;
     type Value;
@@ -63,6 +136,25 @@ where T: PartialOrd
     }
 }
 
+impl<T> MapEntry for Arc<T>
+where T: PartialOrd
+{
+    type Key = T;
+    type Value = T;
+
+    fn new(k: Self::Key, _v: Self::Value) -> Self {
+        Arc::new(k)
+    }
+
+    fn key(&self) -> &Self::Key {
+        self.as_ref()
+    }
+
+    fn value(&self) -> &Self::Value {
+        self.as_ref()
+    }
+}
+
 impl<K, V> MapEntry for (K, V)
 where K: Clone + PartialOrd,
       V: Clone,
@@ -75,11 +167,11 @@ where K: Clone + PartialOrd,
     }
 
     fn key(&self) -> &Self::Key {
-        &(*self).0
+        &self.0
     }
 
     fn value(&self) -> &Self::Value {
-        &(*self).1
+        &self.1
     }
 }
 
@@ -94,49 +186,65 @@ pub trait FiniteMap: Sized {
     fn lookup(&self, k: &Self::Key) -> Option<&Self::Value>;
 }
 
-#[derive(Clone, Debug)]
-struct UnbalancedMap<T>(Rc<Tree<T>>);
+pub struct UnbalancedMap<T, P: SharedPointerKind = RcK>(SharedPointer<Tree<T, P>, P>);
 
-impl<T> FiniteMap for UnbalancedMap<T>
+impl<T, P: SharedPointerKind> Clone for UnbalancedMap<T, P> {
+    fn clone(&self) -> Self {
+        UnbalancedMap(self.0.clone())
+    }
+}
+
+impl<T, P> fmt::Debug for UnbalancedMap<T, P>
+where T: fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("UnbalancedMap").field(&*self.0).finish()
+    }
+}
+
+impl<T, P> FiniteMap for UnbalancedMap<T, P>
 where T: MapEntry,
+      P: SharedPointerKind,
 {
     type Entry = T;
     type Key = T::Key;
     type Value = T::Value;
 
-    fn empty() -> UnbalancedMap<T> {
-        UnbalancedMap(Tree::empty())
+    fn empty() -> UnbalancedMap<T, P> {
+        UnbalancedMap(Tree::<T, P>::empty())
     }
 
     fn insert(&self, e: Self::Entry) -> Result<Self, AlreadyPresent> {
-        fn iter<T>(t: &Rc<Tree<T>>, x: T, candidate: Option<&T>)
-                      -> Result<Rc<Tree<T>>, AlreadyPresent>
+        fn iter<T, P>(t: &SharedPointer<Tree<T, P>, P>, x: T, candidate: Option<&T>)
+                      -> Result<SharedPointer<Tree<T, P>, P>, AlreadyPresent>
         where T: MapEntry,
+              P: SharedPointerKind,
         {
             match **t {
                 Tree::E => {
                     match candidate {
                         Some(c) if c.key() == x.key() => Err(AlreadyPresent),
                         Some(_) | None => {
-                            Ok(Tree::leaf(x))
+                            Ok(Tree::<T, P>::leaf(x))
                         }
                     }
                 },
                 Tree::T(ref left, ref y, ref right) => {
                     if x.key() < y.key() {
-                        Ok(Tree::node(&iter(left, x, candidate)?,
-                                      (*y).clone(),
-                                      right))
+                        Ok(Tree::<T, P>::node(&iter::<T, P>(left, x, candidate)?,
+                                              (*y).clone(),
+                                              right))
                     } else {
-                        Ok(Tree::node(left,
-                                      (*y).clone(),
-                                      &iter(right, x, Some(y))?))
+                        Ok(Tree::<T, P>::node(left,
+                                              (*y).clone(),
+                                              &iter::<T, P>(right, x, Some(y))?))
                     }
                 }
             }
         }
 
-        iter(&self.0, e, None).map(|t| UnbalancedMap(t))
+        iter::<T, P>(&self.0, e, None).map(UnbalancedMap)
     }
 
     fn bind(&self, k: Self::Key, v: Self::Value) -> Self {
@@ -147,16 +255,17 @@ where T: MapEntry,
     }
 
     fn lookup(&self, k: &Self::Key) -> Option<&Self::Value> {
-        fn iter<'a, T>(t: &'a Rc<Tree<T>>, x: &T::Key) -> Option<&'a T::Value>
-        where T: MapEntry,
+        fn iter<'a, T, P>(t: &'a SharedPointer<Tree<T, P>, P>, x: &T::Key) -> Option<&'a T::Value>
+        where T: MapEntry + 'a,
+              P: SharedPointerKind + 'a,
         {
             match **t {
                 Tree::E => None,
                 Tree::T(ref left, ref y, ref right) => {
                     if x < y.key() {
-                        iter(left, x)
+                        iter::<T, P>(left, x)
                     } else if x > y.key() {
-                        iter(right, x)
+                        iter::<T, P>(right, x)
                     } else {
                         Some(y.value())
                     }
@@ -164,49 +273,19 @@ where T: MapEntry,
             }
         }
 
-        iter(&self.0, k)
+        iter::<T, P>(&self.0, k)
     }
 }
 
-fn complete<E>(depth: usize, value: E) -> Rc<Tree<E>>
-where E: Clone,
-{
-    iterate(Tree::leaf(value.clone()),
-            |subtree| {
-                Tree::node(&subtree, value.clone(), &subtree)
-            })
-        .skip(depth-1).next().unwrap()
-}
-
-fn tree_of<E>(size: usize, value: E) -> Rc<Tree<E>>
-where E: Clone
-{
-    fn subtree_size(x: usize) -> usize {
-        ((x - 1) as f64 / 2.0).floor() as usize
+impl<T, P: SharedPointerKind> SharedNodes for UnbalancedMap<T, P> {
+    fn node_bytes() -> usize {
+        allocation_bytes::<Tree<T, P>>()
     }
 
-    fn create2<E>(m: usize, value: E) -> (Rc<Tree<E>>, Rc<Tree<E>>)
-    where E: Clone
+    fn visit_nodes<F>(&self, visit: F)
+    where F: FnMut(usize) -> bool,
     {
-        if m == 0 {
-            (Tree::leaf(value), Tree::empty())
-        } else {
-            (tree_of(m+1, value.clone()), tree_of(m, value))
-        }
-    }
-
-    match size {
-        0 => Tree::empty(),
-        1 => Tree::leaf(value),
-        size if size % 2 == 0 => {
-            let (larger, smaller) = create2(subtree_size(size), value.clone());
-            Tree::node(&larger, value, &smaller)
-        },
-        size if size % 2 == 1 => {
-            let subtree = tree_of(subtree_size(size), value.clone());
-            Tree::node(&subtree, value, &subtree)
-        },
-        _ => unreachable!("all numbers are odd or even"),
+        tree::visit_tree_nodes::<T, P, F>(&self.0, visit)
     }
 }
 
@@ -214,7 +293,52 @@ where E: Clone
 mod tests {
     use super::*;
 
+    use shared::ArcK;
+    use sharing::SharingReport;
     use tree::BinaryTree;
+    use util::iterate;
+
+    fn complete<E>(depth: usize, value: E) -> Rc<Tree<E>>
+    where E: Clone,
+    {
+        iterate(Tree::<E>::leaf(value.clone()),
+                |subtree| {
+                    Tree::<E>::node(subtree, value.clone(), subtree)
+                })
+            .nth(depth-1).unwrap()
+    }
+
+    fn tree_of<E>(size: usize, value: E) -> Rc<Tree<E>>
+    where E: Clone
+    {
+        fn subtree_size(x: usize) -> usize {
+            ((x - 1) as f64 / 2.0).floor() as usize
+        }
+
+        fn create2<E>(m: usize, value: E) -> (Rc<Tree<E>>, Rc<Tree<E>>)
+        where E: Clone
+        {
+            if m == 0 {
+                (Tree::<E>::leaf(value), Tree::<E>::empty())
+            } else {
+                (tree_of(m+1, value.clone()), tree_of(m, value))
+            }
+        }
+
+        match size {
+            0 => Tree::<E>::empty(),
+            1 => Tree::<E>::leaf(value),
+            size if size % 2 == 0 => {
+                let (larger, smaller) = create2(subtree_size(size), value.clone());
+                Tree::<E>::node(&larger, value, &smaller)
+            },
+            size if size % 2 == 1 => {
+                let subtree = tree_of(subtree_size(size), value.clone());
+                Tree::<E>::node(&subtree, value, &subtree)
+            },
+            _ => unreachable!("all numbers are odd or even"),
+        }
+    }
 
     #[test]
     fn empty() {
@@ -230,14 +354,14 @@ mod tests {
 
     #[test]
     fn insert_several() {
-        let t = UnbalancedSet::empty().insert(1).insert(3);
+        let t = UnbalancedSet::<_>::empty().insert(1).insert(3);
         assert!(t.member(&1));
         assert!(t.member(&3));
     }
 
     #[test]
     fn insert_many() {
-        let t = UnbalancedSet::empty().insert(2).insert(1).insert(3);
+        let t = UnbalancedSet::<_>::empty().insert(2).insert(1).insert(3);
         assert!(t.member(&1));
         assert!(t.member(&2));
         assert!(t.member(&3));
@@ -250,6 +374,27 @@ mod tests {
         assert_eq!(2, Rc::strong_count(&(t.0).0));
     }
 
+    #[test]
+    fn sharing_between_set_versions() {
+        let t = UnbalancedSet::<_>::empty().insert(2).insert(1).insert(3);
+        let t2 = t.insert(4);
+
+        // Inserting 4 copies the path 2 -> 3 and replaces the empty
+        // tree right of 3 with a new node holding two empty trees.
+        let report = SharingReport::of(&[t, t2]);
+        assert_eq!(4, report.shared_nodes);
+        assert_eq!(vec![3, 5], report.unique_nodes);
+    }
+
+    #[test]
+    fn sharing_within_one_tree_is_counted_once() {
+        let m = UnbalancedMap::<(u8, u8)>(complete(3, (0, 0)));
+        assert_eq!(7, m.0.count());
+        let report = SharingReport::of(&[m]);
+        assert_eq!(vec![5], report.unique_nodes);
+        assert_eq!(vec![5], report.reachable_nodes);
+    }
+
     #[test]
     fn complete_one() {
         let t = complete(1, ());
@@ -304,11 +449,21 @@ mod tests {
         assert_eq!(3, t.right().unwrap().depth());
     }
 
-    //#[test]
-    fn display_it() {
-        let t = tree_of(5, 1);
-        println!("{}", t);
-        assert_eq!(1, 2);
+    #[test]
+    fn set_macro() {
+        let t = set![2, 1, 3, 1];
+        assert!(t.member(&1));
+        assert!(t.member(&2));
+        assert!(t.member(&3));
+        assert!(!t.member(&4));
+    }
+
+    #[test]
+    fn map_macro() {
+        let m = map!{"zero" => 0u8, "one" => 1u8, "one" => 15u8};
+        assert_eq!(Some(&0), m.lookup(&"zero"));
+        assert_eq!(Some(&1), m.lookup(&"one"));
+        assert_eq!(None, m.lookup(&"two"));
     }
 
     #[test]
@@ -333,5 +488,35 @@ mod tests {
             .bind("one", 1u8);
         let m1 = m.bind("one", 15);
         assert_eq!(Some(&1), m.lookup(&"one"));
+        assert_eq!(Some(&1), m1.lookup(&"one"));
+    }
+
+    #[test]
+    fn sync_map_is_shared_across_threads() {
+        use std::thread;
+
+        let m = UnbalancedMap::<(&str, u8), ArcK>::empty()
+            .bind("zero", 0u8);
+        let m1 = m.clone();
+        let handle = thread::spawn(move || m1.bind("one", 1u8));
+
+        let m2 = handle.join().unwrap();
+        assert_eq!(None, m.lookup(&"one"));
+        assert_eq!(Some(&0), m2.lookup(&"zero"));
+        assert_eq!(Some(&1), m2.lookup(&"one"));
+    }
+
+    #[test]
+    fn sync_set_is_shared_across_threads() {
+        use std::thread;
+
+        let t = UnbalancedSet::<u8, ArcK>::empty().insert(2).insert(1);
+        let t1 = t.clone();
+        let handle = thread::spawn(move || t1.insert(3));
+
+        let t2 = handle.join().unwrap();
+        assert!(!t.member(&3));
+        assert!(t2.member(&1));
+        assert!(t2.member(&3));
     }
 }
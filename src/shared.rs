@@ -0,0 +1,82 @@
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A family of reference counted pointers that the persistent
+/// structures can be built from.
+///
+/// `RcK` keeps the single threaded `Rc` representation, while `ArcK`
+/// swaps in `Arc` so that whole versions can be sent to other threads.
+pub trait SharedPointerKind {
+    type Pointer<T>: Deref<Target = T> + Clone;
+
+    fn new<T>(value: T) -> Self::Pointer<T>;
+    fn ptr_eq<T>(this: &Self::Pointer<T>, other: &Self::Pointer<T>) -> bool;
+    fn strong_count<T>(this: &Self::Pointer<T>) -> usize;
+
+    /// Borrows the value mutably if `this` is the only pointer to it.
+    fn get_mut<T>(this: &mut Self::Pointer<T>) -> Option<&mut T>;
+
+    /// Returns the inner value if `this` was the last pointer to it,
+    /// otherwise just releases `this`.
+    fn into_inner<T>(this: Self::Pointer<T>) -> Option<T>;
+}
+
+/// The pointer type selected by a `SharedPointerKind`.
+pub type SharedPointer<T, P> = <P as SharedPointerKind>::Pointer<T>;
+
+/// Selects `std::rc::Rc`.
+#[derive(Clone, Copy, Debug)]
+pub enum RcK {}
+
+/// Selects `std::sync::Arc`.
+#[derive(Clone, Copy, Debug)]
+pub enum ArcK {}
+
+impl SharedPointerKind for RcK {
+    type Pointer<T> = Rc<T>;
+
+    fn new<T>(value: T) -> Rc<T> {
+        Rc::new(value)
+    }
+
+    fn ptr_eq<T>(this: &Rc<T>, other: &Rc<T>) -> bool {
+        Rc::ptr_eq(this, other)
+    }
+
+    fn strong_count<T>(this: &Rc<T>) -> usize {
+        Rc::strong_count(this)
+    }
+
+    fn get_mut<T>(this: &mut Rc<T>) -> Option<&mut T> {
+        Rc::get_mut(this)
+    }
+
+    fn into_inner<T>(this: Rc<T>) -> Option<T> {
+        Rc::into_inner(this)
+    }
+}
+
+impl SharedPointerKind for ArcK {
+    type Pointer<T> = Arc<T>;
+
+    fn new<T>(value: T) -> Arc<T> {
+        Arc::new(value)
+    }
+
+    fn ptr_eq<T>(this: &Arc<T>, other: &Arc<T>) -> bool {
+        Arc::ptr_eq(this, other)
+    }
+
+    fn strong_count<T>(this: &Arc<T>) -> usize {
+        Arc::strong_count(this)
+    }
+
+    fn get_mut<T>(this: &mut Arc<T>) -> Option<&mut T> {
+        Arc::get_mut(this)
+    }
+
+    fn into_inner<T>(this: Arc<T>) -> Option<T> {
+        Arc::into_inner(this)
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::mem;
+
+/// A persistent structure built out of reference counted nodes, which
+/// different versions may have in common.
+pub trait SharedNodes {
+    /// Estimated size of one node allocation, reference counts included.
+    fn node_bytes() -> usize;
+
+    /// Calls `visit` with the address of every node reachable from this
+    /// version. The nodes below a node are only visited when `visit`
+    /// returns `true` for it, so a walk can skip nodes it has already
+    /// been through.
+    fn visit_nodes<F>(&self, visit: F)
+    where F: FnMut(usize) -> bool;
+}
+
+/// Size of a node allocation behind an `Rc` or `Arc`, which keeps a
+/// strong and a weak count next to the value.
+pub(crate) fn allocation_bytes<T>() -> usize {
+    mem::size_of::<T>() + 2 * mem::size_of::<usize>()
+}
+
+/// How many nodes a set of versions of a structure have in common.
+///
+/// Only the nodes of the structure itself are counted; memory owned by
+/// the elements is not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharingReport {
+    /// Nodes reachable from two or more of the versions.
+    pub shared_nodes: usize,
+    /// For each version, the nodes no other version can reach.
+    pub unique_nodes: Vec<usize>,
+    /// For each version, every node it can reach.
+    pub reachable_nodes: Vec<usize>,
+    pub node_bytes: usize,
+}
+
+/// Which versions reach a node. The last version to reach it is kept
+/// so that each version walks a node only once.
+#[derive(Clone, Copy)]
+enum Owner {
+    Only(usize),
+    Shared(usize),
+}
+
+impl SharingReport {
+    pub fn of<S: SharedNodes>(versions: &[S]) -> SharingReport {
+        let mut owners = HashMap::new();
+        let mut reachable_nodes = vec![0; versions.len()];
+
+        for (i, version) in versions.iter().enumerate() {
+            version.visit_nodes(|node| {
+                match owners.entry(node) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(Owner::Only(i));
+                    },
+                    Entry::Occupied(mut entry) => match *entry.get() {
+                        Owner::Only(last) | Owner::Shared(last) if last == i => return false,
+                        _ => {
+                            entry.insert(Owner::Shared(i));
+                        },
+                    },
+                }
+                reachable_nodes[i] += 1;
+                true
+            });
+        }
+
+        let mut shared_nodes = 0;
+        let mut unique_nodes = vec![0; versions.len()];
+        for owner in owners.values() {
+            match *owner {
+                Owner::Only(i) => unique_nodes[i] += 1,
+                Owner::Shared(_) => shared_nodes += 1,
+            }
+        }
+
+        SharingReport {
+            shared_nodes,
+            unique_nodes,
+            reachable_nodes,
+            node_bytes: S::node_bytes(),
+        }
+    }
+
+    /// Distinct nodes across all of the versions.
+    pub fn total_nodes(&self) -> usize {
+        self.shared_nodes + self.unique_nodes.iter().sum::<usize>()
+    }
+
+    /// Estimated memory held by all of the versions together.
+    pub fn estimated_bytes(&self) -> usize {
+        self.total_nodes() * self.node_bytes
+    }
+
+    /// Estimated memory the versions would need if they shared nothing.
+    pub fn unshared_bytes(&self) -> usize {
+        self.reachable_nodes.iter().sum::<usize>() * self.node_bytes
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod allocations {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// The system allocator, counting allocations per thread so that
+    /// tests running side by side don't see each other's.
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static COUNTING: Counting = Counting;
+
+    /// How many allocations `f` makes on the current thread.
+    pub(crate) fn counting<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+}
@@ -0,0 +1,369 @@
+use std::fmt;
+
+use list::{self, IndexOutOfRange, PersistentList, Sequence};
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+
+enum SkewTree<E, P: SharedPointerKind> {
+    Leaf(E),
+    Node(E, SharedPointer<SkewTree<E, P>, P>, SharedPointer<SkewTree<E, P>, P>),
+}
+
+type Digit<E, P> = (usize, SharedPointer<SkewTree<E, P>, P>);
+
+/// Okasaki's skew binary random-access list.
+///
+/// The list is a sequence of complete binary trees whose sizes follow
+/// the digits of a skew binary number, smallest first. Only the first
+/// two trees can have the same size, so `cons`, `first` and `rest`
+/// take constant time while `lookup` and `update` need O(log n).
+pub struct SkewList<E, P: SharedPointerKind = RcK>(PersistentList<Digit<E, P>, P>);
+
+impl<E: Clone> SkewList<E> {
+    pub fn new() -> Self {
+        SkewList(PersistentList::new())
+    }
+}
+
+impl<E: Clone> SkewList<E, ArcK> {
+    pub fn new_sync() -> Self {
+        SkewList(PersistentList::new_sync())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> SkewList<E, P> {
+    pub fn lookup(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        let mut offset = index;
+        for &(weight, ref tree) in self.0.iter() {
+            if offset < weight {
+                return Ok(lookup_tree::<E, P>(weight, tree, offset));
+            }
+            offset -= weight;
+        }
+        Err(IndexOutOfRange { index, len: index - offset })
+    }
+}
+
+fn lookup_tree<'a, E, P>(weight: usize,
+                         tree: &'a SharedPointer<SkewTree<E, P>, P>,
+                         index: usize) -> &'a E
+where E: 'a,
+      P: SharedPointerKind + 'a,
+{
+    let (mut weight, mut tree, mut index) = (weight, tree, index);
+    loop {
+        match **tree {
+            SkewTree::Leaf(ref x) => return x,
+            SkewTree::Node(ref x, _, _) if index == 0 => return x,
+            SkewTree::Node(_, ref t1, ref t2) => {
+                weight /= 2;
+                if index <= weight {
+                    tree = t1;
+                    index -= 1;
+                } else {
+                    tree = t2;
+                    index -= 1 + weight;
+                }
+            },
+        }
+    }
+}
+
+fn update_tree<E, P>(weight: usize,
+                     tree: &SharedPointer<SkewTree<E, P>, P>,
+                     index: usize,
+                     val: E) -> SharedPointer<SkewTree<E, P>, P>
+where E: Clone,
+      P: SharedPointerKind,
+{
+    match **tree {
+        SkewTree::Leaf(_) => P::new(SkewTree::Leaf(val)),
+        SkewTree::Node(_, ref t1, ref t2) if index == 0 => {
+            P::new(SkewTree::Node(val, t1.clone(), t2.clone()))
+        },
+        SkewTree::Node(ref x, ref t1, ref t2) => {
+            let half = weight / 2;
+            if index <= half {
+                let t1 = update_tree::<E, P>(half, t1, index - 1, val);
+                P::new(SkewTree::Node(x.clone(), t1, t2.clone()))
+            } else {
+                let t2 = update_tree::<E, P>(half, t2, index - 1 - half, val);
+                P::new(SkewTree::Node(x.clone(), t1.clone(), t2))
+            }
+        },
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for SkewList<E, P> {
+    fn clone(&self) -> Self {
+        SkewList(self.0.clone())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for SkewList<E, P> {
+    type Iter<'a> = Iter<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        SkewList(PersistentList::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn cons(&self, el: E) -> Self {
+        if let Some(&(w1, ref t1)) = self.0.first() {
+            let rest = self.0.rest();
+            if let Some(&(w2, ref t2)) = rest.first() {
+                if w1 == w2 {
+                    let tree = P::new(SkewTree::Node(el, t1.clone(), t2.clone()));
+                    return SkewList(rest.rest().cons((1 + w1 + w2, tree)));
+                }
+            }
+        }
+        SkewList(self.0.cons((1, P::new(SkewTree::Leaf(el)))))
+    }
+
+    fn first(&self) -> Option<&E> {
+        self.0.first().map(|(_, tree)| match **tree {
+            SkewTree::Leaf(ref x) | SkewTree::Node(ref x, _, _) => x,
+        })
+    }
+
+    fn rest(&self) -> Self {
+        match self.0.first() {
+            None => self.clone(),
+            Some(&(weight, ref tree)) => match **tree {
+                SkewTree::Leaf(_) => SkewList(self.0.rest()),
+                SkewTree::Node(_, ref t1, ref t2) => {
+                    let half = weight / 2;
+                    SkewList(self.0.rest().cons((half, t2.clone())).cons((half, t1.clone())))
+                },
+            },
+        }
+    }
+
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        let mut offset = index;
+        let mut skipped = Vec::new();
+        let mut spine = self.0.clone();
+        loop {
+            let (weight, tree) = match spine.first() {
+                None => return Err(IndexOutOfRange { index, len: index - offset }),
+                Some(&(weight, ref tree)) => (weight, tree.clone()),
+            };
+            spine = spine.rest();
+            if offset < weight {
+                let updated = spine.cons((weight, update_tree::<E, P>(weight, &tree, offset, val)));
+                let spine = skipped.into_iter().rev().fold(updated, |spine, digit| spine.cons(digit));
+                return Ok(SkewList(spine));
+            }
+            offset -= weight;
+            skipped.push((weight, tree));
+        }
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        let prefix = self.iter().cloned().collect::<Vec<_>>();
+        prefix.into_iter().rev().fold(other.clone(), |list, x| list.cons(x))
+    }
+
+    fn iter(&self) -> Iter<'_, E, P> {
+        Iter { spine: self.0.iter(), pending: Vec::new() }
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|&(weight, _)| weight).sum()
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.lookup(index).ok()
+    }
+
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        self.lookup(index)
+    }
+
+    /// Skips whole trees where it can, so this is O(log n) too.
+    fn drop(&self, n: usize) -> Self {
+        let mut n = n;
+        let mut spine = self.0.clone();
+        while n > 0 {
+            let (weight, tree) = match spine.first() {
+                None => break,
+                Some(&(weight, ref tree)) => (weight, tree.clone()),
+            };
+            spine = spine.rest();
+            if n >= weight {
+                n -= weight;
+                continue;
+            }
+            if let SkewTree::Node(_, ref t1, ref t2) = *tree {
+                let half = weight / 2;
+                n -= 1;
+                if n < half {
+                    spine = spine.cons((half, t2.clone())).cons((half, t1.clone()));
+                } else {
+                    n -= half;
+                    spine = spine.cons((half, t2.clone()));
+                }
+            }
+        }
+        SkewList(spine)
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    spine: list::Iter<'a, Digit<E, P>, P>,
+    pending: Vec<&'a SkewTree<E, P>>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        loop {
+            match self.pending.pop() {
+                Some(SkewTree::Leaf(x)) => return Some(x),
+                Some(SkewTree::Node(x, t1, t2)) => {
+                    self.pending.push(t2);
+                    self.pending.push(t1);
+                    return Some(x);
+                },
+                None => match self.spine.next() {
+                    Some((_, tree)) => self.pending.push(tree),
+                    None => return None,
+                },
+            }
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for SkewList<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for SkewList<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for SkewList<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    fn skew_list_of(n: usize) -> SkewList<usize> {
+        (0..n).rev().fold(SkewList::new(), |l, x| l.cons(x))
+    }
+
+    fn weights(l: &SkewList<usize>) -> Vec<usize> {
+        l.0.iter().map(|&(w, _)| w).collect()
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let l: SkewList<u8> = SkewList::new();
+        assert_eq!(l.first(), None);
+        assert!(l.lookup(0).is_err());
+    }
+
+    #[test]
+    fn cons_follows_skew_binary_digits() {
+        assert_eq!(vec![1], weights(&skew_list_of(1)));
+        assert_eq!(vec![1, 1], weights(&skew_list_of(2)));
+        assert_eq!(vec![3], weights(&skew_list_of(3)));
+        assert_eq!(vec![1, 3], weights(&skew_list_of(4)));
+        assert_eq!(vec![3, 3], weights(&skew_list_of(6)));
+        assert_eq!(vec![7], weights(&skew_list_of(7)));
+    }
+
+    #[test]
+    fn rest_drops_first_item() {
+        let l = skew_list_of(7);
+        let rest = l.rest();
+        assert_eq!(vec![3, 3], weights(&rest));
+        assert_eq!(rest.first(), Some(&1));
+        assert_eq!(rest.len(), 6);
+    }
+
+    #[test]
+    fn lookup_every_index() {
+        let l = skew_list_of(100);
+        for i in 0..100 {
+            assert_eq!(Ok(&i), l.lookup(i).map_err(|_| ()));
+        }
+        assert!(l.lookup(100).is_err());
+        assert_eq!((0..100).collect::<Vec<_>>(), l.iter().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn update_every_index() {
+        let l = skew_list_of(50);
+        for i in 0..50 {
+            let new_l = l.update(i, 100).unwrap();
+            assert_eq!(Some(&100), new_l.nth(i));
+            assert_eq!(Some(&i), l.nth(i));
+            assert_eq!(50, new_l.len());
+        }
+        assert!(l.update(50, 100).is_err());
+    }
+
+    #[test]
+    fn update_copies_only_one_tree_path() {
+        // Weights [1, 3]: updating inside the second tree must leave
+        // the first tree and the untouched subtree shared.
+        let l = skew_list_of(4);
+        let new_l = l.update(2, 9).unwrap();
+
+        let old = l.0.iter().collect::<Vec<_>>();
+        let new = new_l.0.iter().collect::<Vec<_>>();
+        assert!(Rc::ptr_eq(&old[0].1, &new[0].1));
+        assert!(!Rc::ptr_eq(&old[1].1, &new[1].1));
+        match (&*old[1].1, &*new[1].1) {
+            (SkewTree::Node(_, _, r1), SkewTree::Node(_, _, r2)) => assert!(Rc::ptr_eq(r1, r2)),
+            _ => panic!("expected nodes"),
+        }
+    }
+
+    #[test]
+    fn drop_skips_whole_trees() {
+        let l = skew_list_of(100);
+        for n in 0..=100 {
+            let suffix = l.drop(n);
+            assert_eq!(100 - n, suffix.len());
+            assert_eq!((n..100).collect::<Vec<_>>(), suffix.iter().cloned().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn concat_joins_two_lists() {
+        let xs = skew_list_of(3);
+        let ys = skew_list_of(2);
+        let zs = xs.concat(&ys);
+        assert_eq!(vec![&0, &1, &2, &0, &1], zs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn large_list() {
+        let l = skew_list_of(1_000_000);
+        assert_eq!(Ok(&765_432), l.lookup(765_432).map_err(|_| ()));
+        let l = l.update(999_999, 0).unwrap();
+        assert_eq!(Some(&0), l.last());
+    }
+}
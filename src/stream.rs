@@ -0,0 +1,354 @@
+use std::fmt;
+use std::rc::Rc;
+
+use util::Susp;
+
+enum StreamCell<T: 'static> {
+    Nil,
+    Cons(T, Stream<T>),
+}
+
+impl<T: Clone> Clone for StreamCell<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            StreamCell::Nil => StreamCell::Nil,
+            StreamCell::Cons(ref x, ref rest) => StreamCell::Cons(x.clone(), rest.clone()),
+        }
+    }
+}
+
+/// A lazy list, as in chapter 4 of Okasaki.
+///
+/// Each cell is computed the first time it is needed and remembered
+/// from then on, so every version sharing a cell through its `Susp`
+/// sees the work done only once. `append` and `take` are incremental, doing
+/// one step of work per cell forced, while `drop` and `reverse` are
+/// monolithic and do all of theirs when their first cell is forced.
+pub struct Stream<T: 'static>(Susp<StreamCell<T>>);
+
+impl<T> Stream<T> {
+    fn forced(cell: StreamCell<T>) -> Self {
+        Stream(Susp::forced(cell))
+    }
+
+    fn delay<F>(f: F) -> Self
+    where F: FnOnce() -> StreamCell<T> + 'static,
+    {
+        Stream(Susp::delay(f))
+    }
+
+    fn force(&self) -> &StreamCell<T> {
+        self.0.force()
+    }
+
+    pub fn empty() -> Self {
+        Stream::forced(StreamCell::Nil)
+    }
+
+    /// A stream whose first cell is worked out by `f` the first time it
+    /// is needed, with `None` standing for the end of the stream.
+    pub fn lazy<F>(f: F) -> Self
+    where F: FnOnce() -> Option<(T, Stream<T>)> + 'static,
+    {
+        Stream::delay(move || match f() {
+            None => StreamCell::Nil,
+            Some((x, rest)) => StreamCell::Cons(x, rest),
+        })
+    }
+
+    /// Whether the first cell has been computed yet.
+    pub fn is_forced(&self) -> bool {
+        self.0.is_forced()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(*self.force(), StreamCell::Nil)
+    }
+
+    pub fn cons(&self, x: T) -> Self {
+        Stream::forced(StreamCell::Cons(x, self.clone()))
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        match *self.force() {
+            StreamCell::Nil => None,
+            StreamCell::Cons(ref x, _) => Some(x),
+        }
+    }
+
+    pub fn rest(&self) -> Self {
+        match *self.force() {
+            StreamCell::Nil => self.clone(),
+            StreamCell::Cons(_, ref rest) => rest.clone(),
+        }
+    }
+
+    /// Forces the cells one at a time as they are borrowed, so this
+    /// never ends on an infinite stream unless the caller stops.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: self }
+    }
+}
+
+impl<T: Clone + 'static> Stream<T> {
+    pub fn append(&self, other: &Self) -> Self {
+        let (s, t) = (self.clone(), other.clone());
+        Stream::delay(move || match *s.force() {
+            StreamCell::Nil => t.force().clone(),
+            StreamCell::Cons(ref x, ref rest) => StreamCell::Cons(x.clone(), rest.append(&t)),
+        })
+    }
+
+    pub fn take(&self, n: usize) -> Self {
+        let s = self.clone();
+        Stream::delay(move || {
+            if n == 0 {
+                return StreamCell::Nil;
+            }
+            match *s.force() {
+                StreamCell::Nil => StreamCell::Nil,
+                StreamCell::Cons(ref x, ref rest) => StreamCell::Cons(x.clone(), rest.take(n - 1)),
+            }
+        })
+    }
+
+    pub fn drop(&self, n: usize) -> Self {
+        let s = self.clone();
+        Stream::delay(move || {
+            let mut s = s;
+            for _ in 0..n {
+                s = match *s.force() {
+                    StreamCell::Nil => break,
+                    StreamCell::Cons(_, ref rest) => rest.clone(),
+                };
+            }
+            s.force().clone()
+        })
+    }
+
+    pub fn reverse(&self) -> Self {
+        let s = self.clone();
+        Stream::delay(move || {
+            let reversed = s.iter().fold(Stream::empty(), |r, x| r.cons(x.clone()));
+            reversed.force().clone()
+        })
+    }
+
+    /// The infinite stream `init, f(init), f(f(init)), ...`, the lazy
+    /// counterpart of `util::iterate`. Each call of `f` waits until its
+    /// cell is forced.
+    pub fn iterate<F>(init: T, f: F) -> Self
+    where F: Fn(&T) -> T + 'static,
+    {
+        fn after<T, F>(x: T, f: Rc<F>) -> Stream<T>
+        where T: Clone + 'static,
+              F: Fn(&T) -> T + 'static,
+        {
+            Stream::delay(move || {
+                let next = f(&x);
+                StreamCell::Cons(next.clone(), after(next, f))
+            })
+        }
+
+        Stream::forced(StreamCell::Cons(init.clone(), after(init, Rc::new(f))))
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream(self.0.clone())
+    }
+}
+
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        // A long forced stream would otherwise drop one cell inside the
+        // next, so take each uniquely owned cell out before it goes. The
+        // first unforced cell is left to `Susp`, which releases whatever
+        // its computation holds on to without forcing it.
+        let mut cell = self.0.take_unique();
+        while let Some(StreamCell::Cons(_, mut rest)) = cell {
+            cell = rest.0.take_unique();
+        }
+    }
+}
+
+pub struct Iter<'a, T: 'static> {
+    next: &'a Stream<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match *self.next.force() {
+            StreamCell::Nil => None,
+            StreamCell::Cons(ref x, ref rest) => {
+                self.next = rest;
+                Some(x)
+            },
+        }
+    }
+}
+
+impl<T> Default for Stream<T> {
+    fn default() -> Self {
+        Stream::empty()
+    }
+}
+
+/// Shows the cells forced so far, without forcing any more.
+impl<T: fmt::Debug> fmt::Debug for Stream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut next = self;
+        while let Some(cell) = next.0.get() {
+            match *cell {
+                StreamCell::Nil => return list.finish(),
+                StreamCell::Cons(ref x, ref rest) => {
+                    list.entry(x);
+                    next = rest;
+                },
+            }
+        }
+        list.finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    fn stream_of(xs: &[i32]) -> Stream<i32> {
+        xs.iter().rev().fold(Stream::empty(), |s, &x| s.cons(x))
+    }
+
+    fn counted_naturals() -> (Stream<i32>, Rc<Cell<usize>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let s = Stream::iterate(0, move |x| {
+            counter.set(counter.get() + 1);
+            x + 1
+        });
+        (s, calls)
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let s: Stream<u8> = Stream::empty();
+        assert_eq!(s.first(), None);
+        assert!(s.rest().is_empty());
+    }
+
+    #[test]
+    fn cons_and_rest() {
+        let s = stream_of(&[1, 2, 3]);
+        assert_eq!(Some(&1), s.first());
+        assert_eq!(Some(&2), s.rest().first());
+        assert_eq!(vec![&1, &2, &3], s.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn iterate_is_lazy_and_memoized() {
+        let (s, calls) = counted_naturals();
+        assert_eq!(0, calls.get());
+
+        assert_eq!(vec![0, 1, 2, 3, 4], s.iter().take(5).cloned().collect::<Vec<_>>());
+        assert_eq!(4, calls.get());
+
+        let shared = s.clone();
+        assert_eq!(vec![0, 1, 2, 3, 4], shared.iter().take(5).cloned().collect::<Vec<_>>());
+        assert_eq!(4, calls.get());
+    }
+
+    #[test]
+    fn iterate_matches_util_iterate() {
+        let s = Stream::iterate(1u64, |x| x * 2);
+        let expected = ::util::iterate(1u64, |x| x * 2).take(20).collect::<Vec<_>>();
+        assert_eq!(expected, s.iter().take(20).cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn take_is_incremental() {
+        let (s, calls) = counted_naturals();
+        let t = s.take(10);
+        assert!(!t.is_forced());
+        assert_eq!(0, calls.get());
+
+        assert_eq!(Some(&0), t.first());
+        assert_eq!(Some(&1), t.rest().first());
+        assert_eq!(1, calls.get());
+        assert_eq!(10, t.iter().count());
+
+        assert!(s.drop(5).take(0).is_empty());
+        assert_eq!(9, calls.get());
+    }
+
+    #[test]
+    fn append_is_lazy_and_shares_the_suffix() {
+        let xs = stream_of(&[1, 2]);
+        let (naturals, calls) = counted_naturals();
+        let s = xs.append(&naturals);
+        assert!(!s.is_forced());
+
+        assert_eq!(vec![1, 2, 0, 1], s.iter().take(4).cloned().collect::<Vec<_>>());
+        assert_eq!(1, calls.get());
+        assert_eq!(vec![&1, &2], xs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_and_reverse() {
+        let s = stream_of(&[1, 2, 3, 4]);
+        let dropped = s.drop(2);
+        assert!(!dropped.is_forced());
+        assert_eq!(vec![&3, &4], dropped.iter().collect::<Vec<_>>());
+        assert!(s.drop(10).is_empty());
+
+        let reversed = s.reverse();
+        assert!(!reversed.is_forced());
+        assert_eq!(vec![&4, &3, &2, &1], reversed.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lazy_waits_until_needed() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let s = Stream::lazy(move || {
+            counter.set(counter.get() + 1);
+            Some((1, Stream::lazy(|| None)))
+        });
+        assert_eq!(0, calls.get());
+        assert_eq!(vec![&1], s.iter().collect::<Vec<_>>());
+        assert_eq!(Some(&1), s.first());
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn debug_shows_forced_cells_only() {
+        let s = Stream::iterate(0, |x| x + 1);
+        assert_eq!("[0, ..]", format!("{:?}", s));
+        s.iter().nth(2);
+        assert_eq!("[0, 1, 2, ..]", format!("{:?}", s));
+        assert_eq!("[1, 2]", format!("{:?}", stream_of(&[1, 2])));
+    }
+
+    #[test]
+    fn long_forced_stream_drops_without_overflow() {
+        let s = Stream::iterate(0, |x| x + 1).take(1_000_000);
+        assert_eq!(1_000_000, s.iter().count());
+    }
+
+    #[test]
+    fn long_unforced_stream_drops_without_overflow() {
+        let (naturals, calls) = counted_naturals();
+        let mut s = naturals.take(1);
+        for i in 0..1_000_000 {
+            s = s.append(&stream_of(&[i])).take(i as usize);
+        }
+        assert!(!s.is_forced());
+        drop(s);
+        assert_eq!(0, calls.get());
+    }
+}
@@ -1,12 +1,115 @@
-use std::{cmp, fmt};
-use std::rc::Rc;
+use std::{cmp, fmt, mem};
 
+use shared::{RcK, SharedPointer, SharedPointerKind};
 use util::iterate;
 
-#[derive(Debug, PartialEq)]
-pub enum Tree<E> {
+pub enum Tree<E, P: SharedPointerKind = RcK> {
     E,
-    T(Rc<Tree<E>>, E, Rc<Tree<E>>),
+    T(SharedPointer<Tree<E, P>, P>, E, SharedPointer<Tree<E, P>, P>),
+}
+
+impl<E, P: SharedPointerKind> Drop for Tree<E, P> {
+    fn drop(&mut self) {
+        // Degenerate trees are as deep as they are large, so tear down
+        // uniquely owned subtrees without recursing. Every node is moved
+        // out of its allocation and `E` left behind, so nothing new is
+        // allocated and each detached node's own drop returns at once.
+        if let Tree::T(ref mut left, _, ref mut right) = *self {
+            if let Some(tree) = take_unique_node::<E, P>(left) {
+                tear_down(tree);
+            }
+            if let Some(tree) = take_unique_node::<E, P>(right) {
+                tear_down(tree);
+            }
+        }
+    }
+}
+
+/// Drops a detached tree in constant stack space. A uniquely owned left
+/// child is rotated up to the root, reusing its allocation for the rest
+/// of the tree, so only right spines are ever followed.
+fn tear_down<E, P: SharedPointerKind>(mut tree: Tree<E, P>) {
+    loop {
+        let left = match tree {
+            Tree::T(ref mut left, ..) => take_unique_node::<E, P>(left),
+            Tree::E => return,
+        };
+        if let Some(mut top) = left {
+            if let Tree::T(_, _, ref mut cell) = top {
+                if let Tree::T(ref mut left, ..) = tree {
+                    mem::swap(left, cell);
+                }
+                let rest = mem::replace(&mut tree, Tree::E);
+                *P::get_mut(cell).expect("the cell was just taken from the tree") = rest;
+            }
+            tree = top;
+            continue;
+        }
+        tree = match tree {
+            Tree::T(_, _, ref mut right) => match take_unique_node::<E, P>(right) {
+                Some(right) => right,
+                None => return,
+            },
+            Tree::E => return,
+        };
+    }
+}
+
+/// Moves a node out of a tree nothing else points to, leaving `E`.
+fn take_unique_node<E, P>(tree: &mut SharedPointer<Tree<E, P>, P>) -> Option<Tree<E, P>>
+where P: SharedPointerKind,
+{
+    match P::get_mut(tree) {
+        Some(node @ &mut Tree::T(..)) => Some(mem::replace(node, Tree::E)),
+        _ => None,
+    }
+}
+
+/// Walks the nodes of a tree for `SharedNodes`, without recursing so
+/// that degenerate trees can be walked too.
+pub(crate) fn visit_tree_nodes<E, P, F>(root: &SharedPointer<Tree<E, P>, P>, mut visit: F)
+where P: SharedPointerKind,
+      F: FnMut(usize) -> bool,
+{
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        if !visit(&**node as *const Tree<E, P> as usize) {
+            continue;
+        }
+        if let Tree::T(ref left, _, ref right) = **node {
+            pending.push(right);
+            pending.push(left);
+        }
+    }
+}
+
+impl<E, P> fmt::Debug for Tree<E, P>
+where E: fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Tree::E => f.write_str("E"),
+            Tree::T(ref left, ref value, ref right) => {
+                f.debug_tuple("T").field(&**left).field(value).field(&**right).finish()
+            },
+        }
+    }
+}
+
+impl<E, P> PartialEq for Tree<E, P>
+where E: PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Tree::E, Tree::E) => true,
+            (Tree::T(ref l1, ref x, ref r1), Tree::T(ref l2, ref y, ref r2)) => {
+                x == y && **l1 == **l2 && **r1 == **r2
+            },
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -14,24 +117,25 @@ enum Alignment {
     Left, Right
 }
 
-impl<E> fmt::Display for Tree<E>
+impl<E, P> fmt::Display for Tree<E, P>
 where E: fmt::Display,
+      P: SharedPointerKind,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         use self::Alignment::*;
 
-        fn format_value<E>(node: &Tree<E>) -> String
-        where E: fmt::Display {
+        fn format_value<E, P>(node: &Tree<E, P>) -> String
+        where E: fmt::Display, P: SharedPointerKind {
            node.value().map(|v| format!("{}", v)).unwrap_or("( )".to_string())
         }
-        let aligns = vec![Left, Right];
+        let aligns = [Left, Right];
         let depth = self.depth();
         let width = f.width().unwrap_or(3);
         let widths = iterate(width, |w| (2*w)+1)
             .skip(1)
             .take(depth-1)
             .collect::<Vec<_>>();
-        let width = widths.first().unwrap().clone()+1;
+        let width = *widths.first().unwrap()+1;
         write!(f, "{:width$}{: ^width$}", "", format_value(self), width=width)?;
         let mut nodes = vec![self.left(), self.right()];
         for width in widths.into_iter().rev() {
@@ -44,17 +148,17 @@ where E: fmt::Display,
                 let edge = if i % 2 == 0 { "/" } else { "\\ " };
                 write!(f, " {: ^width$} ", edge, width=width-2)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
             let width = cmp::max((width-1)/2, 3);
             for (item, align) in nodes.into_iter().zip(aligns.iter().cycle()) {
-                let item = item.unwrap_or(Tree::empty());
+                let item = item.unwrap_or_else(Tree::<E, P>::empty);
                 match align {
                     Left => {
                         write!(f, "{:width$}", "", width=(width/2)+1)?;
-                        write!(f, "{: ^width$}{:width$}", format_value(item.as_ref()), "", width=width)?;
+                        write!(f, "{: ^width$}{:width$}", format_value(&*item), "", width=width)?;
                     },
                     Right => {
-                        write!(f, "{: ^width$}", format_value(item.as_ref()), width=width)?;
+                        write!(f, "{: ^width$}", format_value(&*item), width=width)?;
                         //write!(f, "{:width$}", "", width=width-1)?;
                     },
                 };
@@ -65,32 +169,36 @@ where E: fmt::Display,
     }
 }
 
-impl<E> Tree<E> {
-    pub fn empty() -> Rc<Self> {
-        Rc::new(Tree::E)
+impl<E, P: SharedPointerKind> Tree<E, P> {
+    pub fn empty() -> SharedPointer<Self, P> {
+        P::new(Tree::E)
     }
 
-    pub fn leaf(x: E) -> Rc<Self> {
-        Rc::new(Tree::T(Tree::empty(), x, Tree::empty()))
+    pub fn leaf(x: E) -> SharedPointer<Self, P> {
+        P::new(Tree::T(Self::empty(), x, Self::empty()))
     }
 
-    pub fn node(left: &Rc<Self>, x: E, right: &Rc<Self>) -> Rc<Self> {
-        Rc::new(Tree::T(Rc::clone(left), x, Rc::clone(right)))
+    pub fn node(left: &SharedPointer<Self, P>,
+                x: E,
+                right: &SharedPointer<Self, P>) -> SharedPointer<Self, P> {
+        P::new(Tree::T(left.clone(), x, right.clone()))
     }
 }
 
 pub trait BinaryTree: Sized {
     type Item;
+    type Kind: SharedPointerKind;
 
     fn value(&self) -> Option<&Self::Item>;
-    fn left(&self)  -> Option<Rc<Self>>;
-    fn right(&self) -> Option<Rc<Self>>;
+    fn left(&self)  -> Option<SharedPointer<Self, Self::Kind>>;
+    fn right(&self) -> Option<SharedPointer<Self, Self::Kind>>;
     fn count(&self) -> usize;
     fn depth(&self) -> usize;
 }
 
-impl<E> BinaryTree for Tree<E> {
+impl<E, P: SharedPointerKind> BinaryTree for Tree<E, P> {
     type Item = E;
+    type Kind = P;
 
     fn value(&self) -> Option<&Self::Item> {
         match self {
@@ -99,17 +207,17 @@ impl<E> BinaryTree for Tree<E> {
         }
     }
 
-    fn left(&self) -> Option<Rc<Self>> {
+    fn left(&self) -> Option<SharedPointer<Self, P>> {
         match self {
             Tree::E => None,
-            Tree::T(ref left, _, _) => Some(Rc::clone(left)),
+            Tree::T(ref left, _, _) => Some(left.clone()),
         }
     }
 
-    fn right(&self) -> Option<Rc<Self>> {
+    fn right(&self) -> Option<SharedPointer<Self, P>> {
         match self {
             Tree::E => None,
-            Tree::T(_, _, ref right) => Some(Rc::clone(right)),
+            Tree::T(_, _, ref right) => Some(right.clone()),
         }
     }
 
@@ -124,12 +232,61 @@ impl<E> BinaryTree for Tree<E> {
         match self {
             Tree::E => 0,
             Tree::T(ref left, _, ref right) => {
-                vec![left.depth(), right.depth()]
-                    .iter()
-                    .max()
-                    .unwrap()
-                    .clone() + 1
+                cmp::max(left.depth(), right.depth()) + 1
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    #[test]
+    fn dropping_degenerate_tree_does_not_overflow() {
+        let mut t = Tree::<u32>::empty();
+        for i in 0..1_000_000 {
+            t = Tree::<u32>::node(&Tree::<u32>::empty(), i, &t);
+        }
+        drop(t);
+    }
+
+    #[test]
+    fn dropping_version_keeps_shared_subtree() {
+        let mut shared = Tree::<u32>::empty();
+        for i in 0..100_000 {
+            shared = Tree::<u32>::node(&shared, i, &Tree::<u32>::empty());
+        }
+        let mut t = Rc::clone(&shared);
+        for i in 0..100_000 {
+            t = Tree::<u32>::node(&Tree::<u32>::empty(), i, &t);
+        }
+
+        drop(t);
+        assert_eq!(1, Rc::strong_count(&shared));
+        assert_eq!(Some(&99_999), shared.value());
+        assert_eq!(Some(&99_998), shared.left().unwrap().value());
+    }
+
+    #[test]
+    fn dropping_a_tree_allocates_nothing() {
+        use sharing::allocations;
+
+        let mut zigzag = Tree::<u32>::empty();
+        for i in 0..1000 {
+            zigzag = if i % 2 == 0 {
+                Tree::<u32>::node(&zigzag, i, &Tree::<u32>::leaf(i))
+            } else {
+                Tree::<u32>::node(&Tree::<u32>::leaf(i), i, &zigzag)
+            };
+        }
+        let shared = Rc::clone(&zigzag);
+        let t = Tree::<u32>::node(&zigzag, 0, &zigzag);
+        drop(zigzag);
+
+        assert_eq!(0, allocations::counting(|| drop(t)));
+        assert_eq!(0, allocations::counting(|| drop(shared)));
+    }
+}
@@ -0,0 +1,617 @@
+use std::{array, fmt};
+use std::iter::{FromIterator, Rev};
+use std::ops::Index;
+use std::slice;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use list::{IndexOutOfRange, Sequence};
+use shared::{ArcK, RcK, SharedPointer, SharedPointerKind};
+use sharing::{allocation_bytes, SharedNodes};
+
+/// Most elements a single chunk holds.
+const CHUNK: usize = 16;
+
+/// A run of elements stored inline and last first, so the front of the
+/// list is the highest slot a version sees and `rest` only has to
+/// shorten the run.
+///
+/// Slots are filled from the bottom up and never change once set, so a
+/// version that sees every filled slot can `cons` into the next one in
+/// place: no other version can see it yet. Every chunk but the first of
+/// a list is full.
+struct Chunk<E, P: SharedPointerKind> {
+    filled: AtomicUsize,
+    slots: [OnceLock<E>; CHUNK],
+    next: Option<ChunkPointer<E, P>>,
+}
+
+type ChunkPointer<E, P> = SharedPointer<Chunk<E, P>, P>;
+
+impl<E, P: SharedPointerKind> Chunk<E, P> {
+    /// A chunk holding `run`, given last element first.
+    fn new<I>(run: I, next: Option<ChunkPointer<E, P>>) -> ChunkPointer<E, P>
+    where I: IntoIterator<Item = E>,
+    {
+        let slots = array::from_fn(|_| OnceLock::new());
+        let mut filled = 0;
+        for (slot, el) in slots.iter().zip(run) {
+            if slot.set(el).is_err() {
+                unreachable!("a new chunk's slots are empty");
+            }
+            filled += 1;
+        }
+        P::new(Chunk { filled: AtomicUsize::new(filled), slots, next })
+    }
+
+    fn element(&self, slot: usize) -> &E {
+        self.slots[slot].get().expect("visible slots are filled")
+    }
+
+    /// The first `visible` slots, front of the list first.
+    fn run(&self, visible: usize) -> Run<'_, E> {
+        Run { slots: self.slots[..visible].iter().rev() }
+    }
+
+    /// Fills the slot just above the `visible` ones with `el`, if no
+    /// other version has filled it already.
+    fn push(&self, visible: usize, el: E) -> Result<(), E> {
+        if visible == CHUNK {
+            return Err(el);
+        }
+        match self.filled.compare_exchange(visible, visible + 1, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => self.slots[visible].set(el),
+            Err(_) => Err(el),
+        }
+    }
+}
+
+impl<E, P: SharedPointerKind> Drop for Chunk<E, P> {
+    fn drop(&mut self) {
+        // Unlink uniquely owned chunks one at a time, as `List` does
+        // with its cells, so a long list doesn't drop recursively.
+        let mut next = self.next.take();
+        while let Some(chunk) = next {
+            next = P::into_inner(chunk).and_then(|mut chunk| chunk.next.take());
+        }
+    }
+}
+
+/// A persistent list that keeps up to `CHUNK` elements inline in each
+/// node.
+///
+/// Versions share chunks the way `PersistentList` versions share cells,
+/// but each allocation carries a whole run of elements, so long lists
+/// of small values need far fewer allocations and pointer hops. A
+/// version may only see the front part of its first chunk. `first`,
+/// `rest` and `len` are O(1), and so is `cons` onto the newest version
+/// of a chunk, which fills the next slot in place; consing onto an
+/// older version copies its part of the first chunk. `nth` and `drop`
+/// skip whole chunks at a time.
+///
+/// Filling slots in place has its costs. A slot stays filled once
+/// claimed, so an element consed onto a version that is then dropped
+/// lives on until the whole chunk is freed, and every later `cons` onto
+/// a version that doesn't see that slot copies the chunk. Slots are
+/// claimed atomically so that `ArcK` versions on different threads
+/// can't claim the same one, and the `RcK` flavour pays for those
+/// atomics too.
+pub struct UnrolledList<E, P: SharedPointerKind = RcK> {
+    len: usize,
+    /// How many elements of the first chunk belong to this version.
+    visible: usize,
+    chunk: Option<ChunkPointer<E, P>>,
+}
+
+impl<E: Clone> UnrolledList<E> {
+    pub fn new() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone> UnrolledList<E, ArcK> {
+    pub fn new_sync() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> UnrolledList<E, P> {
+    /// Puts `elements` in front of `rest`, packing them into as few
+    /// chunks as `cons` would.
+    fn prepend(elements: Vec<E>, rest: &Self) -> Self {
+        elements.into_iter().rev().fold(rest.clone(), |l, el| l.cons(el))
+    }
+
+    /// The chunk holding `index`, how much of it this version sees and
+    /// how far into that part the element is.
+    fn seek(&self, index: usize) -> Option<(&ChunkPointer<E, P>, usize, usize)> {
+        let mut offset = index;
+        let mut visible = self.visible;
+        let mut chunk = self.chunk.as_ref()?;
+        while offset >= visible {
+            offset -= visible;
+            chunk = chunk.next.as_ref()?;
+            visible = CHUNK;
+        }
+        Some((chunk, visible, offset))
+    }
+
+    fn prefix(&self, n: usize) -> Vec<E> {
+        self.iter().take(n).cloned().collect()
+    }
+}
+
+impl<E, P: SharedPointerKind> Clone for UnrolledList<E, P> {
+    fn clone(&self) -> Self {
+        UnrolledList { len: self.len, visible: self.visible, chunk: self.chunk.clone() }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Sequence<E> for UnrolledList<E, P> {
+    type Iter<'a> = Iter<'a, E, P> where E: 'a, P: 'a;
+
+    fn empty() -> Self {
+        UnrolledList { len: 0, visible: 0, chunk: None }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn cons(&self, el: E) -> Self {
+        let chunk = match self.chunk {
+            None => return UnrolledList { len: 1, visible: 1, chunk: Some(Chunk::<E, P>::new(Some(el), None)) },
+            Some(ref chunk) => chunk,
+        };
+        let el = match chunk.push(self.visible, el) {
+            Ok(()) => return UnrolledList {
+                len: self.len + 1,
+                visible: self.visible + 1,
+                chunk: Some(chunk.clone()),
+            },
+            Err(el) => el,
+        };
+        if self.visible == CHUNK {
+            let chunk = Chunk::<E, P>::new(Some(el), Some(chunk.clone()));
+            return UnrolledList { len: self.len + 1, visible: 1, chunk: Some(chunk) };
+        }
+
+        // Another version has filled the slot above ours, so copy our
+        // part of the chunk to keep every later chunk full.
+        let mut run = chunk.run(self.visible).rev().cloned().collect::<Vec<_>>();
+        run.push(el);
+        UnrolledList {
+            len: self.len + 1,
+            visible: run.len(),
+            chunk: Some(Chunk::<E, P>::new(run, chunk.next.clone())),
+        }
+    }
+
+    fn first(&self) -> Option<&E> {
+        self.chunk.as_ref().map(|chunk| chunk.element(self.visible - 1))
+    }
+
+    fn rest(&self) -> Self {
+        match self.chunk {
+            None => self.clone(),
+            Some(ref chunk) if self.visible > 1 => UnrolledList {
+                len: self.len - 1,
+                visible: self.visible - 1,
+                chunk: Some(chunk.clone()),
+            },
+            Some(ref chunk) => UnrolledList {
+                len: self.len - 1,
+                visible: chunk.next.as_ref().map_or(0, |_| CHUNK),
+                chunk: chunk.next.clone(),
+            },
+        }
+    }
+
+    /// Copies the chunks in front of the one holding `index`, and that
+    /// one, but shares everything after it.
+    fn update(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        if index >= self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+
+        let mut skipped = Vec::new();
+        let mut offset = index;
+        let mut visible = self.visible;
+        let mut chunk = self.chunk.as_ref().expect("a non-empty list has a chunk");
+        while offset >= visible {
+            skipped.push((chunk, visible));
+            offset -= visible;
+            chunk = chunk.next.as_ref().expect("len counts every chunk");
+            visible = CHUNK;
+        }
+
+        let mut run = chunk.run(visible).rev().cloned().collect::<Vec<_>>();
+        run[visible - 1 - offset] = val;
+        let mut next = Chunk::<E, P>::new(run, chunk.next.clone());
+        for (chunk, visible) in skipped.into_iter().rev() {
+            next = Chunk::<E, P>::new(chunk.run(visible).rev().cloned(), Some(next));
+        }
+        Ok(UnrolledList { len: self.len, visible: self.visible, chunk: Some(next) })
+    }
+
+    fn concat(&self, other: &Self) -> Self {
+        Self::prepend(self.prefix(self.len), other)
+    }
+
+    fn iter(&self) -> Iter<'_, E, P> {
+        match self.chunk {
+            None => Iter { run: Run { slots: [].iter().rev() }, next: None },
+            Some(ref chunk) => Iter { run: chunk.run(self.visible), next: chunk.next.as_deref() },
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn nth(&self, index: usize) -> Option<&E> {
+        self.seek(index).map(|(chunk, visible, offset)| chunk.element(visible - 1 - offset))
+    }
+
+    fn get(&self, index: usize) -> Result<&E, IndexOutOfRange> {
+        self.nth(index).ok_or(IndexOutOfRange { index, len: self.len })
+    }
+
+    fn split_at(&self, index: usize) -> Result<(Self, Self), IndexOutOfRange> {
+        if index > self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        Ok((self.take(index), self.drop(index)))
+    }
+
+    fn insert_at(&self, index: usize, val: E) -> Result<Self, IndexOutOfRange> {
+        if index > self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        let mut prefix = self.prefix(index);
+        prefix.push(val);
+        Ok(Self::prepend(prefix, &self.drop(index)))
+    }
+
+    fn remove_at(&self, index: usize) -> Result<Self, IndexOutOfRange> {
+        if index >= self.len {
+            return Err(IndexOutOfRange { index, len: self.len });
+        }
+        Ok(Self::prepend(self.prefix(index), &self.drop(index + 1)))
+    }
+
+    fn last(&self) -> Option<&E> {
+        self.len.checked_sub(1).and_then(|index| self.nth(index))
+    }
+
+    /// Copies the first `n` elements into fresh chunks, or shares the
+    /// whole list when it has no more than `n`.
+    fn take(&self, n: usize) -> Self {
+        if n >= self.len {
+            self.clone()
+        } else {
+            Self::prepend(self.prefix(n), &Self::empty())
+        }
+    }
+
+    /// Skips whole chunks where it can; nothing is copied.
+    fn drop(&self, n: usize) -> Self {
+        match self.seek(n) {
+            None => Self::empty(),
+            Some((chunk, visible, offset)) => UnrolledList {
+                len: self.len - n,
+                visible: visible - offset,
+                chunk: Some(chunk.clone()),
+            },
+        }
+    }
+}
+
+/// The elements a version sees in one chunk, front of the list first.
+struct Run<'a, E: 'a> {
+    slots: Rev<slice::Iter<'a, OnceLock<E>>>,
+}
+
+impl<'a, E> Iterator for Run<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.slots.next().map(|slot| slot.get().expect("visible slots are filled"))
+    }
+}
+
+impl<'a, E> DoubleEndedIterator for Run<'a, E> {
+    fn next_back(&mut self) -> Option<&'a E> {
+        self.slots.next_back().map(|slot| slot.get().expect("visible slots are filled"))
+    }
+}
+
+pub struct Iter<'a, E: 'a, P: SharedPointerKind + 'a> {
+    run: Run<'a, E>,
+    next: Option<&'a Chunk<E, P>>,
+}
+
+impl<'a, E, P: SharedPointerKind> Iterator for Iter<'a, E, P> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        loop {
+            if let Some(x) = self.run.next() {
+                return Some(x);
+            }
+            let chunk = self.next?;
+            self.run = chunk.run(CHUNK);
+            self.next = chunk.next.as_deref();
+        }
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Default for UnrolledList<E, P> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<E, P> fmt::Debug for UnrolledList<E, P>
+where E: Clone + fmt::Debug,
+      P: SharedPointerKind,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<E, P> PartialEq for UnrolledList<E, P>
+where E: Clone + PartialEq,
+      P: SharedPointerKind,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> Index<usize> for UnrolledList<E, P> {
+    type Output = E;
+
+    fn index(&self, index: usize) -> &E {
+        match self.get(index) {
+            Ok(el) => el,
+            Err(_) => panic!("index out of bounds: the len is {} but the index is {}", self.len, index),
+        }
+    }
+}
+
+impl<'a, E: Clone, P: SharedPointerKind> IntoIterator for &'a UnrolledList<E, P> {
+    type Item = &'a E;
+    type IntoIter = Iter<'a, E, P>;
+
+    fn into_iter(self) -> Iter<'a, E, P> {
+        self.iter()
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> FromIterator<E> for UnrolledList<E, P> {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        Self::prepend(iter.into_iter().collect(), &Self::empty())
+    }
+}
+
+impl<E: Clone, P: SharedPointerKind> From<Vec<E>> for UnrolledList<E, P> {
+    fn from(elements: Vec<E>) -> Self {
+        Self::prepend(elements, &Self::empty())
+    }
+}
+
+impl<E, P: SharedPointerKind> SharedNodes for UnrolledList<E, P> {
+    /// A chunk, with its slots inline.
+    fn node_bytes() -> usize {
+        allocation_bytes::<Chunk<E, P>>()
+    }
+
+    fn visit_nodes<F>(&self, mut visit: F)
+    where F: FnMut(usize) -> bool,
+    {
+        let mut chunk = self.chunk.as_deref();
+        while let Some(node) = chunk {
+            if !visit(node as *const Chunk<E, P> as usize) {
+                break;
+            }
+            chunk = node.next.as_deref();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::thread;
+
+    use list::PersistentList;
+    use sharing::SharingReport;
+
+    fn unrolled_list_of(n: usize) -> UnrolledList<usize> {
+        (0..n).rev().fold(UnrolledList::new(), |l, x| l.cons(x))
+    }
+
+    fn chunk_sizes(l: &UnrolledList<usize>) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut chunk = l.chunk.as_deref();
+        while let Some(node) = chunk {
+            sizes.push(node.filled.load(Ordering::Acquire));
+            chunk = node.next.as_deref();
+        }
+        sizes
+    }
+
+    #[test]
+    fn first_on_nil() {
+        let l: UnrolledList<u8> = UnrolledList::new();
+        assert_eq!(l.first(), None);
+        assert!(l.rest().is_empty());
+        assert_eq!(None, l.last());
+    }
+
+    #[test]
+    fn cons_fills_the_first_chunk() {
+        assert_eq!(vec![1], chunk_sizes(&unrolled_list_of(1)));
+        assert_eq!(vec![CHUNK], chunk_sizes(&unrolled_list_of(CHUNK)));
+        assert_eq!(vec![1, CHUNK], chunk_sizes(&unrolled_list_of(CHUNK + 1)));
+        assert_eq!(vec![5, CHUNK, CHUNK], chunk_sizes(&unrolled_list_of(2 * CHUNK + 5)));
+    }
+
+    #[test]
+    fn rest_walks_every_element() {
+        let mut l = unrolled_list_of(50);
+        for i in 0..50 {
+            assert_eq!(Some(&i), l.first());
+            assert_eq!(50 - i, l.len());
+            l = l.rest();
+        }
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn rest_shares_the_chunk() {
+        let l = unrolled_list_of(3);
+        let rest = l.rest();
+        assert!(Rc::ptr_eq(l.chunk.as_ref().unwrap(), rest.chunk.as_ref().unwrap()));
+        assert_eq!(vec![&1, &2], rest.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cons_onto_an_old_version_leaves_newer_ones_alone() {
+        let l = unrolled_list_of(3);
+        let rest = l.rest();
+        let other = rest.cons(9);
+        assert_eq!(vec![&0, &1, &2], l.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&9, &1, &2], other.iter().collect::<Vec<_>>());
+        assert!(!Rc::ptr_eq(l.chunk.as_ref().unwrap(), other.chunk.as_ref().unwrap()));
+
+        let again = l.cons(8);
+        let twice = l.cons(7);
+        assert!(Rc::ptr_eq(l.chunk.as_ref().unwrap(), again.chunk.as_ref().unwrap()));
+        assert_eq!(vec![&8, &0, &1, &2], again.iter().collect::<Vec<_>>());
+        assert_eq!(vec![&7, &0, &1, &2], twice.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn building_by_cons_fills_chunks_in_place() {
+        #[derive(Debug)]
+        struct Counted(Rc<Cell<usize>>);
+
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                Counted(self.0.clone())
+            }
+        }
+
+        let clones = Rc::new(Cell::new(0));
+        let l = (0..1000).fold(UnrolledList::new(), |l, _| l.cons(Counted(clones.clone())));
+        assert_eq!(0, clones.get());
+        assert_eq!(1000, l.len());
+        assert_eq!(1000 / CHUNK + 1, SharingReport::of(&[l]).total_nodes());
+    }
+
+    #[test]
+    fn consed_element_lives_as_long_as_its_chunk() {
+        let l = (0..3).fold(UnrolledList::new(), |l, x| l.cons(Rc::new(x)));
+        let el = Rc::new(9);
+        drop(l.cons(el.clone()));
+        assert_eq!(2, Rc::strong_count(&el));
+
+        let copied = l.cons(Rc::new(8));
+        assert!(!Rc::ptr_eq(l.chunk.as_ref().unwrap(), copied.chunk.as_ref().unwrap()));
+        assert_eq!(2, Rc::strong_count(&el));
+        drop(l);
+        assert_eq!(1, Rc::strong_count(&el));
+        assert_eq!(4, copied.len());
+    }
+
+    #[test]
+    fn nth_and_drop_skip_chunks() {
+        let l = unrolled_list_of(100);
+        for i in 0..100 {
+            assert_eq!(Some(&i), l.nth(i));
+            let suffix = l.drop(i);
+            assert_eq!(100 - i, suffix.len());
+            assert_eq!((i..100).collect::<Vec<_>>(), suffix.iter().cloned().collect::<Vec<_>>());
+        }
+        assert_eq!(None, l.nth(100));
+        assert!(l.drop(100).is_empty());
+        assert_eq!(Some(&99), l.last());
+    }
+
+    #[test]
+    fn update_every_index() {
+        let l = unrolled_list_of(50);
+        for i in 0..50 {
+            let new_l = l.update(i, 100).unwrap();
+            assert_eq!(Some(&100), new_l.nth(i));
+            assert_eq!(Some(&i), l.nth(i));
+            assert_eq!(50, new_l.len());
+        }
+        assert_eq!(Err(IndexOutOfRange { index: 50, len: 50 }), l.update(50, 100));
+    }
+
+    #[test]
+    fn update_shares_later_chunks() {
+        let l = unrolled_list_of(3 * CHUNK);
+        let new_l = l.update(1, 9).unwrap();
+        let old_next = l.chunk.as_ref().unwrap().next.as_ref().unwrap();
+        let new_next = new_l.chunk.as_ref().unwrap().next.as_ref().unwrap();
+        assert!(Rc::ptr_eq(old_next, new_next));
+    }
+
+    #[test]
+    fn concat_and_positional_edits() {
+        let xs = unrolled_list_of(20);
+        let ys = unrolled_list_of(5);
+        let zs = xs.concat(&ys);
+        let expected = (0..20).chain(0..5).collect::<Vec<_>>();
+        assert_eq!(expected, zs.iter().cloned().collect::<Vec<_>>());
+
+        let inserted = xs.insert_at(17, 100).unwrap();
+        assert_eq!(Ok(&100), inserted.get(17));
+        assert_eq!(xs, inserted.remove_at(17).unwrap());
+
+        let (front, back) = xs.split_at(7).unwrap();
+        assert_eq!(front.concat(&back), xs);
+        assert_eq!(Err(IndexOutOfRange { index: 21, len: 20 }), xs.split_at(21));
+    }
+
+    #[test]
+    fn collect_packs_full_chunks() {
+        let l = (0..40).collect::<UnrolledList<_>>();
+        assert_eq!(vec![8, CHUNK, CHUNK], chunk_sizes(&l));
+        assert_eq!(l, unrolled_list_of(40));
+        assert_eq!(20, l[20]);
+    }
+
+    #[test]
+    fn fewer_nodes_than_a_persistent_list() {
+        let unrolled = (0..1000).collect::<UnrolledList<u32>>();
+        let list = (0..1000).collect::<PersistentList<u32>>();
+        let chunks = SharingReport::of(&[unrolled.clone(), unrolled.rest()]);
+        let cells = SharingReport::of(&[list.clone(), list.rest()]);
+        assert_eq!(1000 / CHUNK + 1, chunks.total_nodes());
+        assert!(chunks.total_nodes() * 10 < cells.total_nodes());
+    }
+
+    #[test]
+    fn dropping_long_list_does_not_overflow() {
+        let l = (0..1_000_000).collect::<UnrolledList<_>>();
+        assert_eq!(Some(&999_999), l.last());
+    }
+
+    #[test]
+    fn sync_list_is_shared_across_threads() {
+        let l = (0..100).fold(UnrolledList::new_sync(), |l, x| l.cons(x));
+        let shared = l.clone();
+        let sum = thread::spawn(move || shared.iter().sum::<i32>()).join().unwrap();
+        assert_eq!(4950, sum);
+    }
+}
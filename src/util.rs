@@ -1,3 +1,8 @@
+use std::{fmt, mem};
+use std::any::Any;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::rc::Rc;
+
 pub struct Iterate<I, F> {
     current: I,
     f: F,
@@ -21,6 +26,151 @@ where F: Fn(&I) -> I,
     Iterate { current: init, f}
 }
 
+type Thunk<T> = Box<dyn FnOnce() -> T>;
+
+struct SuspNode<T> {
+    value: OnceCell<T>,
+    thunk: Cell<Option<Thunk<T>>>,
+    /// Set when the computation panicked, which leaves no thunk behind
+    /// just as one still running does.
+    poisoned: Cell<bool>,
+}
+
+thread_local! {
+    /// Suspensions let go of while another one is being released on
+    /// this thread, waiting for it to finish. `None` when no release is
+    /// under way.
+    static RELEASED: RefCell<Option<Vec<Rc<dyn Any>>>> = const { RefCell::new(None) };
+}
+
+/// A memoized suspension, Okasaki's `$e`.
+///
+/// The computation runs the first time any clone of the suspension is
+/// forced, and every clone sees its result from then on. That is what
+/// lets amortized lazy structures pay for work once even when old
+/// versions are used again.
+///
+/// Dropping the last clone never forces it. Suspensions can be nested
+/// as deep as a lazy structure is long, both in their values and in
+/// whatever their computations capture, so one that is let go of while
+/// another is being released waits its turn instead of being released
+/// inside it, and no chain of them can overflow the stack.
+pub struct Susp<T: 'static>(Rc<SuspNode<T>>);
+
+impl<T> Susp<T> {
+    pub fn delay<F>(f: F) -> Self
+    where F: FnOnce() -> T + 'static,
+    {
+        Susp(Rc::new(SuspNode {
+            value: OnceCell::new(),
+            thunk: Cell::new(Some(Box::new(f))),
+            poisoned: Cell::new(false),
+        }))
+    }
+
+    /// A suspension whose value is already known.
+    pub fn forced(value: T) -> Self {
+        Susp(Rc::new(SuspNode {
+            value: OnceCell::from(value),
+            thunk: Cell::new(None),
+            poisoned: Cell::new(false),
+        }))
+    }
+
+    pub fn force(&self) -> &T {
+        self.0.value.get_or_init(|| {
+            let thunk = match self.0.thunk.take() {
+                Some(thunk) => thunk,
+                None if self.0.poisoned.get() => panic!("suspension forced after its computation panicked"),
+                None => panic!("suspension forced while it was being evaluated"),
+            };
+            let poison = Poison(&self.0.poisoned);
+            let value = thunk();
+            mem::forget(poison);
+            value
+        })
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.0.value.get().is_some()
+    }
+
+    /// The value, if it has been forced, without forcing it.
+    pub fn get(&self) -> Option<&T> {
+        self.0.value.get()
+    }
+
+    /// Takes the value out of a forced suspension nothing else shares.
+    pub(crate) fn take_unique(&mut self) -> Option<T> {
+        Rc::get_mut(&mut self.0).and_then(|node| node.value.take())
+    }
+}
+
+impl<T> Drop for Susp<T> {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.0) != 1 {
+            return;
+        }
+        let outermost = RELEASED.try_with(|released| match *released.borrow_mut() {
+            Some(ref mut waiting) => {
+                waiting.push(self.0.clone());
+                false
+            },
+            ref mut idle => {
+                *idle = Some(Vec::new());
+                true
+            },
+        });
+        if outermost != Ok(true) {
+            return;
+        }
+
+        let _release = Releasing;
+        if let Some(node) = Rc::get_mut(&mut self.0) {
+            drop(node.value.take());
+            drop(node.thunk.take());
+        }
+        while let Some(next) = RELEASED.with(|released| released.borrow_mut().as_mut().and_then(Vec::pop)) {
+            drop(next);
+        }
+    }
+}
+
+/// Poisons a suspension if its computation unwinds before it is
+/// forgotten.
+struct Poison<'a>(&'a Cell<bool>);
+
+impl<'a> Drop for Poison<'a> {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+/// Marks the end of the outermost release, even if dropping something
+/// along the way panics.
+struct Releasing;
+
+impl Drop for Releasing {
+    fn drop(&mut self) {
+        let _ = RELEASED.try_with(|released| released.borrow_mut().take());
+    }
+}
+
+impl<T> Clone for Susp<T> {
+    fn clone(&self) -> Self {
+        Susp(self.0.clone())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Susp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("Susp").field(value).finish(),
+            None => f.write_str("Susp(..)"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,4 +186,71 @@ mod tests {
         let res = iterate(1, |x| x * 2);
         assert_eq!(vec![1, 2, 4, 8], res.take(4).collect::<Vec<u8>>());
     }
+
+    fn counted(value: i32) -> (Susp<i32>, Rc<Cell<usize>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let susp = Susp::delay(move || {
+            counter.set(counter.get() + 1);
+            value
+        });
+        (susp, calls)
+    }
+
+    #[test]
+    fn delay_does_not_evaluate() {
+        let (susp, calls) = counted(3);
+        assert!(!susp.is_forced());
+        assert_eq!(None, susp.get());
+        assert_eq!(0, calls.get());
+    }
+
+    #[test]
+    fn force_evaluates_once() {
+        let (susp, calls) = counted(3);
+        assert_eq!(&3, susp.force());
+        assert_eq!(&3, susp.force());
+        assert!(susp.is_forced());
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn clones_share_the_result() {
+        let (susp, calls) = counted(3);
+        let shared = susp.clone();
+        assert_eq!(&3, shared.force());
+        assert!(susp.is_forced());
+        assert_eq!(&3, susp.force());
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn debug_does_not_force() {
+        let (susp, calls) = counted(3);
+        assert_eq!("Susp(..)", format!("{:?}", susp));
+        susp.force();
+        assert_eq!("Susp(3)", format!("{:?}", susp));
+        assert_eq!(1, calls.get());
+        assert_eq!("Susp(4)", format!("{:?}", Susp::forced(4)));
+    }
+
+    #[test]
+    #[should_panic(expected = "suspension forced while it was being evaluated")]
+    fn forcing_itself_panics() {
+        let cell = Rc::new(OnceCell::new());
+        let inner = cell.clone();
+        let susp = Susp::delay(move || *inner.get().map(Susp::force).unwrap_or(&0));
+        let _ = cell.set(susp.clone());
+        susp.force();
+    }
+
+    #[test]
+    #[should_panic(expected = "suspension forced after its computation panicked")]
+    fn forcing_after_a_panic_says_so() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let susp = Susp::delay(|| -> i32 { panic!("the computation failed") });
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| susp.force())).is_err());
+        susp.force();
+    }
 }